@@ -1,31 +1,91 @@
-use std::path::Path;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use clap::{App, AppSettings, Arg};
 
+use crate::config::{self, Settings};
+use crate::description::DescriptionFormat;
+use crate::filter;
+use crate::money::Locale;
+use crate::sources::Source;
+
+const PRODUCTS_FILE: &str = "products.csv";
+const VARIATIONS_FILE: &str = "variations.csv";
+const CATEGORIES_FILE: &str = "categories.csv";
+const DEFAULT_TIMEOUT: u64 = 30;
+
 #[derive(Debug)]
 pub struct Args {
     pub verbose: bool,
+    pub config_file: Option<String>,
     pub file: String,
     pub url: String,
-    pub limit: u32,
+    pub limit: usize,
     pub output_dir: Option<String>,
     pub output_products_file: String,
     pub output_variations_file: String,
+    pub output_categories_file: String,
+    pub output_iluria_categories_file: String,
+    pub output_id_map_file: String,
+    pub output_redirects_files: (String, String, String),
+    pub output_catalog_file: String,
+    pub output_changes_file: String,
+    pub output_stock_file: String,
+    pub output_lint_file: String,
+    pub output_report_files: (String, String),
     pub force: bool,
     pub simulate: bool,
+    pub locale: Locale,
+    pub price_rules_file: Option<String>,
+    pub category_map_file: Option<String>,
+    pub id_map_file: Option<String>,
+    pub redirects: bool,
+    pub picture_columns: Option<usize>,
+    pub pictures_separator: Option<String>,
+    pub previous_catalog_file: Option<String>,
+    pub stock_update: bool,
+    pub lint_rules_file: Option<String>,
+    pub shipping_defaults_file: Option<String>,
+    pub description_rules_file: Option<String>,
+    pub description_format: DescriptionFormat,
+    pub pictures_dir: Option<String>,
+    pub pictures_url: Option<String>,
+    pub new_url: Option<String>,
+    pub strict: bool,
+    pub only_ids: Option<HashSet<String>>,
+    pub offset: usize,
+    pub filter: Option<String>,
+    pub sample: Option<usize>,
+    pub seed: Option<u64>,
+    pub fixtures_dir: Option<String>,
+    pub timeout: Duration,
+    pub source: Source,
 }
 
 impl Args {
     pub fn new() -> Args {
-        Args::new_from(&mut std::env::args_os()).unwrap_or_else(|err| err.exit())
+        Args::new_from(&mut std::env::args_os(), config::default_config_file())
+            .unwrap_or_else(|err| err.exit())
     }
-    fn new_from<I, T>(args: I) -> Result<Args, clap::Error>
+
+    // The default config file is only used without --config, tests pass none
+    // so they don't read the one of the user.
+    fn new_from<I, T>(args: I, default_config_file: Option<PathBuf>) -> Result<Args, clap::Error>
     where
         I: Iterator<Item = T>,
         T: Into<std::ffi::OsString> + Clone,
     {
         let args = Args::get_args_app().get_matches_from_safe(args)?;
-        Ok(Args::get_config_from_cl(args))
+        config::load_settings(
+            args.value_of("config"),
+            default_config_file,
+            args.value_of("profile"),
+        )
+        .and_then(|(config_file, settings)| Args::get_config_from_cl(args, config_file, settings))
+        .map_err(|e| clap::Error::with_description(&e, clap::ErrorKind::InvalidValue))
     }
 
     fn get_args_app<'a, 'b>() -> App<'a, 'b> {
@@ -40,39 +100,15 @@ impl Args {
                     .index(1)
                     .required(true)
                     .help("File with products and variations")
-                    .validator(|file| {
-                        let path = std::path::Path::new(&file);
-                        if path.exists() && path.is_file() {
-                            Ok(())
-                        } else {
-                            Err(format!("Input file '{}' does not exist", file))
-                        }
-                    }),
+                    .validator(existing_file("Input")),
             )
             .arg(
                 Arg::with_name("url")
                     .takes_value(true)
                     .index(2)
-                    .required(true)
-                    .help("Base url to get products")
-                    .validator(|supplied_url| {
-                        let url_result = url::Url::parse(&supplied_url);
-                        if let Ok(url) = url_result {
-                            if url.cannot_be_a_base() {
-                                return Err(format!("Url '{}' has to be absolute.", url));
-                            }
-                            if url.scheme() == "https" && url.scheme() == "http" {
-                                return Err(format!(
-                                    "Scheme '{}' has to be http or https.",
-                                    url.scheme()
-                                ));
-                            } else {
-                                Ok(())
-                            }
-                        } else {
-                            Err("Invalid url format.".to_owned())
-                        }
-                    }),
+                    .required(false)
+                    .help("Base url to get products, required unless set in the config file")
+                    .validator(valid_url),
             )
             .arg(
                 Arg::with_name("limit")
@@ -82,7 +118,7 @@ impl Args {
                     .required(false)
                     .help("How many item to process, by default all items will be processed")
                     .validator(|l| {
-                        l.parse::<u32>()
+                        l.parse::<usize>()
                             .map(|_| ())
                             .map_err(|_| "Limit has to be an integer".to_owned())
                     }),
@@ -94,21 +130,13 @@ impl Args {
                     .takes_value(true)
                     .required(false)
                     .help("Sets the output files directory, if not informed output will be printed to screen")
-                    .validator(|dir| {
-                        let path = std::path::Path::new(&dir);
-                        if path.exists() && path.is_dir() {
-                            Ok(())
-                        } else {
-                            Err(format!("Output directory '{}' does not exist", dir))
-                        }
-                    }),
+                    .validator(existing_dir),
             )
             .arg(
                 Arg::with_name("products-file")
                     .short("p")
                     .long("products-file")
                     .takes_value(true)
-                    .requires("output")
                     .help("Sets the output file name for the products file")
             )
             .arg(
@@ -116,14 +144,18 @@ impl Args {
                     .short("r")
                     .long("variations-file")
                     .takes_value(true)
-                    .requires("output")
                     .help("Sets the output file name for the variations file")
             )
+            .arg(
+                Arg::with_name("categories-file")
+                    .long("categories-file")
+                    .takes_value(true)
+                    .help("Sets the output file name for the categories file")
+            )
             .arg(
                 Arg::with_name("force")
                     .short("f")
                     .long("force")
-                    .requires("output")
                     .help("Overwrite output files if they exist"),
             )
             .arg(
@@ -132,6 +164,255 @@ impl Args {
                     .long("simulate")
                     .help("Simulate calls to scraping endpoints"),
             )
+            .arg(
+                Arg::with_name("source")
+                    .long("source")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Platform of the store the products are read from, iluria (default) or nuvemshop")
+                    .validator(|s| s.parse::<Source>().map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("fixtures")
+                    .long("fixtures")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Directory with pages to use when simulating, <id>.html for a product, or a product.html template for all of them")
+                    .validator(|dir| {
+                        if Path::new(&dir).is_dir() {
+                            Ok(())
+                        } else {
+                            Err(format!("Fixtures directory '{}' does not exist", dir))
+                        }
+                    }),
+            )
+            .arg(
+                Arg::with_name("timeout")
+                    .long("timeout")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Seconds to wait for each product page, 30 by default")
+                    .validator(|t| match t.parse::<u64>() {
+                        Ok(t) if t > 0 => Ok(()),
+                        _ => Err("Timeout has to be a number of seconds".to_owned()),
+                    }),
+            )
+            .arg(
+                Arg::with_name("locale")
+                    .long("locale")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Number format of the prices in the products file and pages, pt-BR (default) or en-US")
+                    .validator(|l| l.parse::<Locale>().map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("price-rules")
+                    .long("price-rules")
+                    .takes_value(true)
+                    .required(false)
+                    .help("TOML file with rules to change the prices before they are exported")
+                    .validator(existing_file("Price rules")),
+            )
+            .arg(
+                Arg::with_name("category-map")
+                    .long("category-map")
+                    .takes_value(true)
+                    .required(false)
+                    .help("CSV file mapping Iluria categories to the categories of the new store")
+                    .validator(existing_file("Category map")),
+            )
+            .arg(
+                Arg::with_name("id-map")
+                    .long("id-map")
+                    .takes_value(true)
+                    .required(false)
+                    .help("CSV file with the ids assigned in a previous run (ids.csv), so products keep them")
+                    .validator(existing_file("Id map")),
+            )
+            .arg(
+                Arg::with_name("redirects")
+                    .long("redirects")
+                    .help("Write redirects from the Iluria urls to the new store urls, for nginx, Apache and as a CSV file"),
+            )
+            .arg(
+                Arg::with_name("picture-columns")
+                    .long("picture-columns")
+                    .takes_value(true)
+                    .required(false)
+                    .help("How many picture columns the products file has, 5 by default, use 0 for as many as needed")
+                    .validator(|c| {
+                        c.parse::<usize>()
+                            .map(|_| ())
+                            .map_err(|_| "Picture columns has to be an integer".to_owned())
+                    }),
+            )
+            .arg(
+                Arg::with_name("pictures-separator")
+                    .long("pictures-separator")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("picture-columns")
+                    .help("Export all pictures in a single column, separated by this text"),
+            )
+            .arg(
+                Arg::with_name("previous")
+                    .long("previous")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Catalog of a previous run (catalog.json), to export only what was created, updated or deleted since then")
+                    .validator(existing_file("Previous catalog")),
+            )
+            .arg(
+                Arg::with_name("stock-update")
+                    .long("stock-update")
+                    .conflicts_with_all(&[
+                        "previous",
+                        "redirects",
+                        "category-map",
+                        "only-ids",
+                        "offset",
+                        "filter",
+                        "sample",
+                    ])
+                    .help("Only write the stock and prices of the products file, without getting the products pages"),
+            )
+            .arg(
+                Arg::with_name("lint-rules")
+                    .long("lint-rules")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("stock-update")
+                    .help("TOML file with the severity of each validation of the products: error, warning or off")
+                    .validator(existing_file("Lint rules")),
+            )
+            .arg(
+                Arg::with_name("shipping-defaults")
+                    .long("shipping-defaults")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("stock-update")
+                    .help("TOML file with the weight and dimensions of products that have none, by category")
+                    .validator(existing_file("Shipping defaults")),
+            )
+            .arg(
+                Arg::with_name("description-rules")
+                    .long("description-rules")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("stock-update")
+                    .help("TOML file with how descriptions are cleaned: allowed tags, styles and the domains of links and images")
+                    .validator(existing_file("Description rules")),
+            )
+            .arg(
+                Arg::with_name("description-format")
+                    .long("description-format")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("stock-update")
+                    .help("Format of the exported descriptions, html (default), markdown or text")
+                    .validator(|f| f.parse::<DescriptionFormat>().map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("pictures-dir")
+                    .long("pictures-dir")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("stock-update")
+                    .help("Directory to download the pictures of the products and the images of the descriptions to")
+                    .validator(existing_dir),
+            )
+            .arg(
+                Arg::with_name("pictures-url")
+                    .long("pictures-url")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("stock-update")
+                    .help("Url where the pictures directory is going to be hosted, for the exported pictures")
+                    .validator(valid_url),
+            )
+            .arg(
+                Arg::with_name("new-url")
+                    .long("new-url")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("stock-update")
+                    .help("Url of the new store, for the canonical urls and the links of the descriptions")
+                    .validator(valid_url),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("strict")
+                    .conflicts_with("stock-update")
+                    .help("Do not export the products if the validation finds errors"),
+            )
+            .arg(
+                Arg::with_name("only-ids")
+                    .long("only-ids")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Only export the products with these ids, separated by commas"),
+            )
+            .arg(
+                Arg::with_name("offset")
+                    .long("offset")
+                    .takes_value(true)
+                    .required(false)
+                    .help("How many products to skip before the ones that are processed")
+                    .validator(|o| {
+                        o.parse::<usize>()
+                            .map(|_| ())
+                            .map_err(|_| "Offset has to be an integer".to_owned())
+                    }),
+            )
+            .arg(
+                Arg::with_name("filter")
+                    .long("filter")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Only export the products that match this filter, like: vendor == \"X\" && price > 50")
+                    .validator(|f| filter::parse(&f).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("sample")
+                    .long("sample")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("limit")
+                    .help("Export only this many products, chosen at random")
+                    .validator(|s| {
+                        s.parse::<usize>()
+                            .map(|_| ())
+                            .map_err(|_| "Sample has to be an integer".to_owned())
+                    }),
+            )
+            .arg(
+                Arg::with_name("seed")
+                    .long("seed")
+                    .takes_value(true)
+                    .required(false)
+                    .requires("sample")
+                    .help("Seed of the random sample, to choose the same products again")
+                    .validator(|s| {
+                        s.parse::<u64>()
+                            .map(|_| ())
+                            .map_err(|_| "Seed has to be an integer".to_owned())
+                    }),
+            )
+            .arg(
+                Arg::with_name("config")
+                    .long("config")
+                    .takes_value(true)
+                    .required(false)
+                    .help("TOML config file, by default the config.toml file at the iluria-export directory of the user config directory is used, if it exists")
+                    .validator(existing_file("Config")),
+            )
+            .arg(
+                Arg::with_name("profile")
+                    .long("profile")
+                    .takes_value(true)
+                    .required(false)
+                    .help("Profile of the config file to use, with the settings of one store"),
+            )
             .arg(
                 Arg::with_name("v")
                     .short("v")
@@ -142,78 +423,296 @@ impl Args {
             )
     }
 
-    fn get_config_from_cl(args: clap::ArgMatches) -> Args {
+    // Values from the command line override the ones from the config file.
+    fn get_config_from_cl(
+        args: clap::ArgMatches,
+        config_file: Option<PathBuf>,
+        settings: Settings,
+    ) -> Result<Args, String> {
         let verbose = args.occurrences_of("v") > 0;
         let file = args
             .value_of("file")
             .expect("Should have file as it is required")
             .to_owned();
-        let url = args
-            .value_of("url")
-            .expect("Should have url as it is required")
-            .to_owned();
+        let value = |name: &str, setting: Option<String>| -> Option<String> {
+            args.value_of(name).map(|s| s.to_owned()).or(setting)
+        };
+        let flag = |name: &str, setting: Option<bool>| -> bool {
+            args.is_present(name) || setting.unwrap_or(false)
+        };
+        let url = value("url", settings.url).unwrap_or_default();
         let limit = match args.value_of("limit") {
-            Some(l) => l.parse::<u32>().expect("Limit should be a number."),
-            None => 0,
+            Some(l) => l.parse::<usize>().expect("Limit should be a number."),
+            None => settings.limit.unwrap_or(0),
+        };
+        let timeout = Duration::from_secs(match args.value_of("timeout") {
+            Some(t) => t.parse::<u64>().expect("Timeout should be a number."),
+            None => settings.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        });
+        let locale = match value("locale", settings.locale) {
+            Some(l) => l.parse::<Locale>()?,
+            None => Locale::default(),
+        };
+        let source = match value("source", settings.source) {
+            Some(s) => s.parse::<Source>()?,
+            None => Source::default(),
+        };
+        let description_format = match value("description-format", settings.description_format) {
+            Some(f) => f.parse::<DescriptionFormat>()?,
+            None => DescriptionFormat::default(),
         };
-        Args {
+        let (picture_columns, pictures_separator) = match args.value_of("picture-columns") {
+            Some(c) => (
+                Some(
+                    c.parse::<usize>()
+                        .expect("Picture columns should be a number."),
+                ),
+                value("pictures-separator", None),
+            ),
+            None => (
+                settings.picture_columns,
+                value("pictures-separator", settings.pictures_separator),
+            ),
+        };
+        Ok(Args {
             verbose,
+            config_file: config_file.map(|file| file.to_string_lossy().into_owned()),
             file,
             url,
             limit,
-            output_dir: args.value_of("output").map(|s| s.to_owned()),
-            output_products_file: args
-                .value_of("products-file")
-                .or(Some("products.csv"))
-                .unwrap()
-                .to_owned(),
-            output_variations_file: args
-                .value_of("variations-file")
-                .or(Some("variations.csv"))
-                .unwrap()
-                .to_owned(),
-            force: args.is_present("force"),
-            simulate: args.is_present("simulate"),
-        }
+            output_dir: value("output", settings.output),
+            output_products_file: value("products-file", settings.products_file)
+                .unwrap_or_else(|| PRODUCTS_FILE.to_owned()),
+            output_variations_file: value("variations-file", settings.variations_file)
+                .unwrap_or_else(|| VARIATIONS_FILE.to_owned()),
+            output_categories_file: value("categories-file", settings.categories_file)
+                .unwrap_or_else(|| CATEGORIES_FILE.to_owned()),
+            output_iluria_categories_file: "iluria-categories.csv".to_owned(),
+            output_id_map_file: "ids.csv".to_owned(),
+            output_redirects_files: (
+                "redirects.csv".to_owned(),
+                "redirects-nginx.conf".to_owned(),
+                "redirects.htaccess".to_owned(),
+            ),
+            output_catalog_file: "catalog.json".to_owned(),
+            output_changes_file: "changes.txt".to_owned(),
+            output_stock_file: "stock.csv".to_owned(),
+            output_lint_file: "lint.csv".to_owned(),
+            output_report_files: ("report.html".to_owned(), "report.md".to_owned()),
+            force: flag("force", settings.force),
+            simulate: flag("simulate", settings.simulate),
+            locale,
+            price_rules_file: value("price-rules", settings.price_rules),
+            category_map_file: value("category-map", settings.category_map),
+            id_map_file: value("id-map", settings.id_map),
+            redirects: flag("redirects", settings.redirects),
+            picture_columns,
+            pictures_separator,
+            previous_catalog_file: args.value_of("previous").map(|s| s.to_owned()),
+            stock_update: args.is_present("stock-update"),
+            lint_rules_file: value("lint-rules", settings.lint_rules),
+            shipping_defaults_file: value("shipping-defaults", settings.shipping_defaults),
+            description_rules_file: value("description-rules", settings.description_rules),
+            description_format,
+            pictures_dir: value("pictures-dir", settings.pictures_dir),
+            pictures_url: value("pictures-url", settings.pictures_url),
+            new_url: value("new-url", settings.new_url),
+            strict: flag("strict", settings.strict),
+            only_ids: args.value_of("only-ids").map(|ids| {
+                ids.split(',')
+                    .map(|id| id.trim().to_owned())
+                    .filter(|id| !id.is_empty())
+                    .collect()
+            }),
+            offset: args
+                .value_of("offset")
+                .map(|o| o.parse::<usize>().expect("Offset should be a number."))
+                .unwrap_or(0),
+            filter: args.value_of("filter").map(|f| f.to_owned()),
+            sample: args
+                .value_of("sample")
+                .map(|s| s.parse::<usize>().expect("Sample should be a number.")),
+            seed: args
+                .value_of("seed")
+                .map(|s| s.parse::<u64>().expect("Seed should be a number.")),
+            fixtures_dir: value("fixtures", settings.fixtures),
+            timeout,
+            source,
+        })
     }
 
     pub fn get_output_files(&self) -> (Option<String>, Option<String>) {
+        (
+            self.get_output_file(&self.output_products_file),
+            self.get_output_file(&self.output_variations_file),
+        )
+    }
+
+    // Files for each kind of change in sync mode, like "products-create.csv".
+    pub fn get_sync_output_files(&self, change: &str) -> (Option<String>, Option<String>) {
+        (
+            self.get_output_file(&Args::with_suffix(&self.output_products_file, change)),
+            self.get_output_file(&Args::with_suffix(&self.output_variations_file, change)),
+        )
+    }
+
+    fn with_suffix(file_name: &str, suffix: &str) -> String {
+        match file_name.rfind('.') {
+            Some(position) if position > 0 => format!(
+                "{}-{}{}",
+                &file_name[..position],
+                suffix,
+                &file_name[position..]
+            ),
+            _ => format!("{}-{}", file_name, suffix),
+        }
+    }
+
+    pub fn get_output_file(&self, file_name: &str) -> Option<String> {
+        self.output_dir.as_ref().map(|output| {
+            Path::new(output)
+                .join(file_name)
+                .to_string_lossy()
+                .as_ref()
+                .to_owned()
+        })
+    }
+
+    // Values from the config file were not checked by the command line parser.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.stock_update {
+            if self.url.is_empty() {
+                return Err(
+                    "The url is required, pass it as an argument or set it in the config file."
+                        .to_owned(),
+                );
+            }
+            valid_url(self.url.clone())?;
+        }
         match &self.output_dir {
-            None => (None, None),
-            Some(output) => {
-                let output_path = Path::new(output);
-                (
-                    Some(
-                        output_path
-                            .join(&self.output_products_file)
-                            .to_string_lossy()
-                            .as_ref()
-                            .to_owned(),
-                    ),
-                    Some(
-                        output_path
-                            .join(&self.output_variations_file)
-                            .to_string_lossy()
-                            .as_ref()
-                            .to_owned(),
-                    ),
+            Some(dir) => existing_dir(dir.clone())?,
+            None if self.redirects || self.previous_catalog_file.is_some() => {
+                return Err("Redirects and incremental sync need an output directory.".to_owned())
+            }
+            None if self.force
+                || self.output_products_file != PRODUCTS_FILE
+                || self.output_variations_file != VARIATIONS_FILE
+                || self.output_categories_file != CATEGORIES_FILE =>
+            {
+                return Err(
+                    "Output file names and --force need an output directory (--output).".to_owned(),
                 )
             }
+            None => {}
         }
-    }
-    pub fn validate(&self) -> Result<(), String> {
-        let (products_file, variations_file) = self.get_output_files();
-        if Args::file_exists(&products_file)? && !self.force {
-            return Err(format!(
-                "Output products file exists at '{}', use --force to overwrite.",
-                products_file.unwrap()
-            ));
+        // The command line already refuses them, but the config file can set
+        // the pictures directory, which stock updates would not use.
+        if self.stock_update
+            && (self.offset != 0
+                || self.filter.is_some()
+                || self.sample.is_some()
+                || self.only_ids.is_some()
+                || self.pictures_dir.is_some()
+                || self.previous_catalog_file.is_some())
+        {
+            return Err("Stock updates read only the report, they can't be used with --only-ids, --offset, --filter, --sample, --pictures-dir or --previous.".to_owned());
+        }
+        if let Some(url) = &self.new_url {
+            valid_url(url.clone())?;
         }
-        if Args::file_exists(&variations_file)? && !self.force {
-            return Err(format!(
-                "Output variations file exists at '{}', use --force to overwrite.",
-                variations_file.unwrap()
-            ));
+        if let Some(dir) = &self.pictures_dir {
+            existing_dir(dir.clone())?;
+            match &self.pictures_url {
+                Some(url) => valid_url(url.clone())?,
+                None => {
+                    return Err(
+                        "Downloading pictures needs the url where they will be hosted \
+                                (--pictures-url)."
+                            .to_owned(),
+                    )
+                }
+            }
+        }
+        // Products left out would be deleted from the new store.
+        if self.previous_catalog_file.is_some()
+            && (self.limit != 0
+                || self.offset != 0
+                || self.filter.is_some()
+                || self.sample.is_some()
+                || self.only_ids.is_some())
+        {
+            return Err("Incremental sync needs every product, it can't be used with --limit, --offset, --filter, --sample or --only-ids.".to_owned());
+        }
+        if self.timeout.is_zero() {
+            return Err("Timeout has to be a number of seconds.".to_owned());
+        }
+        if self.fixtures_dir.is_some() && !self.simulate {
+            return Err("Fixtures are only used when simulating, use --simulate.".to_owned());
+        }
+        for (description, file) in [
+            ("Price rules", &self.price_rules_file),
+            ("Category map", &self.category_map_file),
+            ("Id map", &self.id_map_file),
+            ("Lint rules", &self.lint_rules_file),
+            ("Shipping defaults", &self.shipping_defaults_file),
+            ("Description rules", &self.description_rules_file),
+        ] {
+            if let Some(file) = file {
+                existing_file(description)(file.clone())?;
+            }
+        }
+        if self.stock_update {
+            return self.validate_output_files(vec![
+                ("stock", self.output_stock_file.clone()),
+                ("ids", self.output_id_map_file.clone()),
+            ]);
+        }
+        let mut output_files = vec![
+            ("categories", self.output_categories_file.clone()),
+            (
+                "Iluria categories",
+                self.output_iluria_categories_file.clone(),
+            ),
+            ("ids", self.output_id_map_file.clone()),
+            ("catalog", self.output_catalog_file.clone()),
+            ("validation", self.output_lint_file.clone()),
+            ("HTML report", self.output_report_files.0.clone()),
+            ("report", self.output_report_files.1.clone()),
+        ];
+        if self.previous_catalog_file.is_some() {
+            output_files.push(("changes", self.output_changes_file.clone()));
+            for change in ["create", "update", "delete"] {
+                output_files.push((
+                    "products",
+                    Args::with_suffix(&self.output_products_file, change),
+                ));
+                output_files.push((
+                    "variations",
+                    Args::with_suffix(&self.output_variations_file, change),
+                ));
+            }
+        } else {
+            output_files.push(("products", self.output_products_file.clone()));
+            output_files.push(("variations", self.output_variations_file.clone()));
+        }
+        if self.redirects {
+            output_files.push(("redirects", self.output_redirects_files.0.clone()));
+            output_files.push(("nginx redirects", self.output_redirects_files.1.clone()));
+            output_files.push(("Apache redirects", self.output_redirects_files.2.clone()));
+        }
+        self.validate_output_files(output_files)
+    }
+
+    fn validate_output_files(&self, output_files: Vec<(&str, String)>) -> Result<(), String> {
+        for (description, file_name) in output_files {
+            let file = self.get_output_file(&file_name);
+            if Args::file_exists(&file)? && !self.force {
+                return Err(format!(
+                    "Output {} file exists at '{}', use --force to overwrite.",
+                    description,
+                    file.unwrap()
+                ));
+            }
         }
         Ok(())
     }
@@ -237,6 +736,45 @@ impl Args {
     }
 }
 
+fn existing_file(description: &'static str) -> impl Fn(String) -> Result<(), String> {
+    move |file| {
+        let path = Path::new(&file);
+        if path.exists() && path.is_file() {
+            Ok(())
+        } else {
+            Err(format!("{} file '{}' does not exist", description, file))
+        }
+    }
+}
+
+fn existing_dir(dir: String) -> Result<(), String> {
+    let path = Path::new(&dir);
+    if path.exists() && path.is_dir() {
+        Ok(())
+    } else {
+        Err(format!("Directory '{}' does not exist", dir))
+    }
+}
+
+fn valid_url(supplied_url: String) -> Result<(), String> {
+    let url_result = url::Url::parse(&supplied_url);
+    if let Ok(url) = url_result {
+        if url.cannot_be_a_base() {
+            return Err(format!("Url '{}' has to be absolute.", url));
+        }
+        if url.scheme() != "https" && url.scheme() != "http" {
+            Err(format!(
+                "Scheme '{}' has to be http or https.",
+                url.scheme()
+            ))
+        } else {
+            Ok(())
+        }
+    } else {
+        Err("Invalid url format.".to_owned())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -252,7 +790,7 @@ mod tests {
             .unwrap()
             .to_owned();
         let url = "http://foo";
-        let args = Args::new_from(["iluria-export", &file, url, "--verbose"].iter())
+        let args = Args::new_from(["iluria-export", &file, url, "--verbose"].iter(), None)
             .map_err(|e| e.to_string())?;
         assert!(args.verbose);
         assert_eq!(file, args.file);
@@ -266,7 +804,7 @@ mod tests {
         let file_path = std::env::temp_dir().join(format!("{}", rand::thread_rng().gen::<f64>()));
         let file = file_path.to_string_lossy();
         let url = "http://foo";
-        Args::new_from(["iluria-export", &file, url, "--verbose"].iter())
+        Args::new_from(["iluria-export", &file, url, "--verbose"].iter(), None)
             .map_err(|e| e.to_string())
             .unwrap();
     }
@@ -281,8 +819,117 @@ mod tests {
             .unwrap()
             .to_owned();
         let url = "mailto:x@sdjlkfsdljk.com";
-        Args::new_from(["iluria-export", &file, url, "--verbose"].iter())
+        Args::new_from(["iluria-export", &file, url, "--verbose"].iter(), None)
             .map_err(|e| e.to_string())
             .unwrap();
     }
+
+    #[test]
+    fn command_line_overrides_config_file() -> Result<(), String> {
+        let file = std::env::current_exe()
+            .map_err(|_| "Can't find exe.")?
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let config = std::env::temp_dir().join(format!(
+            "iluria-export-config-{}.toml",
+            rand::thread_rng().gen::<u64>()
+        ));
+        std::fs::write(
+            &config,
+            "url = \"http://config\"\nlimit = 5\nforce = true\n",
+        )
+        .map_err(|e| e.to_string())?;
+        let config = config.to_string_lossy().into_owned();
+        let args = Args::new_from(
+            [
+                "iluria-export",
+                &file,
+                "http://foo",
+                "-l",
+                "3",
+                "--config",
+                &config,
+            ]
+            .iter(),
+            None,
+        )
+        .map_err(|e| e.to_string())?;
+        assert_eq!("http://foo", args.url);
+        assert_eq!(3, args.limit);
+        assert!(args.force);
+        assert_eq!(Some(config), args.config_file);
+        assert_eq!(
+            "Output file names and --force need an output directory (--output).",
+            args.validate().unwrap_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_sync_needs_every_product() -> Result<(), String> {
+        let file = std::env::current_exe()
+            .map_err(|_| "Can't find exe.")?
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let output = std::env::temp_dir().to_string_lossy().into_owned();
+        let args = Args::new_from(
+            [
+                "iluria-export",
+                &file,
+                "http://foo",
+                "-o",
+                &output,
+                "--previous",
+                &file,
+                "-l",
+                "3",
+            ]
+            .iter(),
+            None,
+        )
+        .map_err(|e| e.to_string())?;
+        assert!(args
+            .validate()
+            .unwrap_err()
+            .starts_with("Incremental sync needs every product"));
+        Ok(())
+    }
+
+    #[test]
+    fn stock_updates_refuse_options_they_would_ignore() -> Result<(), String> {
+        let file = std::env::current_exe()
+            .map_err(|_| "Can't find exe.")?
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let output = std::env::temp_dir().to_string_lossy().into_owned();
+        let config = std::env::temp_dir().join(format!(
+            "iluria-export-config-{}.toml",
+            rand::thread_rng().gen::<u64>()
+        ));
+        std::fs::write(&config, format!("pictures-dir = {:?}\n", output))
+            .map_err(|e| e.to_string())?;
+        let args = Args::new_from(
+            [
+                "iluria-export",
+                &file,
+                "--stock-update",
+                "-o",
+                &output,
+                "--config",
+                &config.to_string_lossy(),
+            ]
+            .iter(),
+            None,
+        );
+        std::fs::remove_file(&config).map_err(|e| e.to_string())?;
+        assert!(args
+            .map_err(|e| e.to_string())?
+            .validate()
+            .unwrap_err()
+            .starts_with("Stock updates read only the report"));
+        Ok(())
+    }
 }
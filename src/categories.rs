@@ -0,0 +1,323 @@
+use crate::{run::Product, slug::UniqueSlugs};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+pub const PATH_SEPARATOR: &str = " > ";
+
+#[derive(Debug, Deserialize)]
+pub struct CategoryMapping {
+    iluria_category: String,
+    target_category: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoryFound {
+    iluria_category: String,
+    target_category: Option<String>,
+    products: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Category {
+    id: u32,
+    parent_id: Option<u32>,
+    name: String,
+    slug: String,
+    position: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct CategoryTree {
+    categories: Vec<Category>,
+    ids: HashMap<Vec<String>, u32>,
+    children_slugs: HashMap<Option<u32>, UniqueSlugs>,
+}
+
+impl CategoryTree {
+    // Every level of every product's category path becomes a category, with
+    // ids and positions following the order in which they were first seen.
+    pub fn from_products(products: &[Product]) -> CategoryTree {
+        let mut tree = CategoryTree::default();
+        let mut children_count: HashMap<Option<u32>, u32> = HashMap::new();
+        for product in products {
+            let mut parent_id = None;
+            for depth in 1..=product.categories.len() {
+                let path = &product.categories[..depth];
+                let id = match tree.ids.get(path) {
+                    Some(&id) => id,
+                    None => {
+                        let id = tree.categories.len() as u32 + 1;
+                        let position = children_count.entry(parent_id).or_insert(0);
+                        *position += 1;
+                        let name = path[depth - 1].clone();
+                        let slug = tree
+                            .children_slugs
+                            .entry(parent_id)
+                            .or_default()
+                            .slugify(&name, &format!("categoria-{}", id));
+                        tree.categories.push(Category {
+                            id,
+                            parent_id,
+                            slug,
+                            name,
+                            position: *position,
+                        });
+                        tree.ids.insert(path.to_vec(), id);
+                        id
+                    }
+                };
+                parent_id = Some(id);
+            }
+        }
+        tree
+    }
+
+    pub fn id_for(&self, categories: &[String]) -> Option<u32> {
+        self.ids.get(categories).copied()
+    }
+
+    // The path of the category page in the new store, made of the slugs of
+    // the category and its parents, like "/roupas/camisetas".
+    pub fn url_path(&self, categories: &[String]) -> Option<String> {
+        let mut id = self.id_for(categories);
+        let mut slugs = vec![];
+        while let Some(category) = id.map(|id| &self.categories[id as usize - 1]) {
+            slugs.insert(0, category.slug.as_str());
+            id = category.parent_id;
+        }
+        if slugs.is_empty() {
+            None
+        } else {
+            Some(format!("/{}", slugs.join("/")))
+        }
+    }
+
+    pub fn top_level_slugs(&self) -> impl Iterator<Item = &str> {
+        self.categories
+            .iter()
+            .filter(|category| category.parent_id.is_none())
+            .map(|category| category.slug.as_str())
+    }
+
+    pub fn into_categories(self) -> Vec<Category> {
+        self.categories
+    }
+}
+
+pub fn load_mappings(file: &str) -> Result<Vec<CategoryMapping>, String> {
+    let bytes = fs::read(file)
+        .map_err(|e| format!("Error when opening category map file '{}': {}", file, e))?;
+    let text = decode_map(&bytes);
+    let mut rdr = csv::Reader::from_reader(text.as_bytes());
+    let mappings = rdr
+        .deserialize()
+        .map(|result| {
+            result.map_err(|e| format!("Could not read category map file '{}': {}", file, e))
+        })
+        .collect::<Result<Vec<CategoryMapping>, String>>()?;
+    printlnv!("Category mappings: {:?}", mappings);
+    Ok(mappings)
+}
+
+// The map is written by hand, so it is usually saved as UTF-8, with or without
+// a BOM, but a copy of iluria-categories.csv saved by a spreadsheet keeps its
+// Windows-1252.
+fn decode_map(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_owned(),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(bytes).0.into_owned(),
+    }
+}
+
+// Replaces the Iluria category of each product with the target one and returns
+// every distinct Iluria category found, in the order they were first seen.
+pub fn map_categories(
+    mappings: Option<&[CategoryMapping]>,
+    products: &mut [Product],
+) -> Vec<CategoryFound> {
+    let mut categories_found: Vec<CategoryFound> = vec![];
+    let mut category_positions = HashMap::new();
+    for product in products.iter_mut() {
+        let iluria_category = category_path(product);
+        if iluria_category.is_empty() {
+            continue;
+        }
+        if let Some(&position) = category_positions.get(&iluria_category) {
+            let category_found: &mut CategoryFound = &mut categories_found[position];
+            category_found.products += 1;
+            if let Some(target_category) = &category_found.target_category {
+                set_category_path(product, target_category);
+            }
+            continue;
+        }
+        let target_category = mappings.and_then(|mappings| {
+            mappings
+                .iter()
+                .find_map(|mapping| mapping.target_for(&iluria_category))
+        });
+        match &target_category {
+            Some(target_category) => {
+                printlnv!(
+                    "Category '{}' mapped to '{}'.",
+                    iluria_category,
+                    target_category
+                );
+                set_category_path(product, target_category);
+            }
+            None if mappings.is_some() => {
+                printlnpb!(
+                    "Warning: category '{}' (product {}) is not mapped, it will be exported as is.",
+                    iluria_category,
+                    product.id
+                );
+            }
+            None => {}
+        }
+        category_positions.insert(iluria_category.clone(), categories_found.len());
+        categories_found.push(CategoryFound {
+            iluria_category,
+            target_category,
+            products: 1,
+        });
+    }
+    categories_found
+}
+
+pub fn category_path(product: &Product) -> String {
+    product.categories.join(PATH_SEPARATOR)
+}
+
+fn set_category_path(product: &mut Product, path: &str) {
+    product.categories = path
+        .split(PATH_SEPARATOR.trim())
+        .map(|level| level.trim().to_owned())
+        .filter(|level| !level.is_empty())
+        .collect();
+}
+
+impl CategoryMapping {
+    // Each `*` in the Iluria category matches any text, which replaces the
+    // `*` in the same position of the target category, if there is one.
+    fn target_for(&self, iluria_category: &str) -> Option<String> {
+        let pattern: Vec<char> = self.iluria_category.trim().chars().collect();
+        let text: Vec<char> = iluria_category.chars().collect();
+        let captures = glob_captures(&pattern, &text, 0)?
+            .into_iter()
+            .map(|(start, end)| text[start..end].iter().collect::<String>())
+            .collect::<Vec<_>>();
+        let mut captures = captures.into_iter();
+        let mut target = String::new();
+        for part in self.target_category.trim().split('*').enumerate() {
+            if part.0 > 0 {
+                target.push_str(&captures.next().unwrap_or_default());
+            }
+            target.push_str(part.1);
+        }
+        Some(target)
+    }
+}
+
+fn glob_captures(pattern: &[char], text: &[char], offset: usize) -> Option<Vec<(usize, usize)>> {
+    match pattern.first() {
+        None if text.is_empty() => Some(vec![]),
+        None => None,
+        Some('*') => (0..=text.len()).find_map(|taken| {
+            glob_captures(&pattern[1..], &text[taken..], offset + taken).map(|mut captures| {
+                captures.insert(0, (offset, offset + taken));
+                captures
+            })
+        }),
+        Some(c)
+            if text
+                .first()
+                .is_some_and(|t| t.to_lowercase().eq(c.to_lowercase())) =>
+        {
+            glob_captures(&pattern[1..], &text[1..], offset + 1)
+        }
+        Some(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn mapping(iluria_category: &str, target_category: &str) -> CategoryMapping {
+        CategoryMapping {
+            iluria_category: iluria_category.to_owned(),
+            target_category: target_category.to_owned(),
+        }
+    }
+
+    #[test]
+    fn builds_tree_with_every_level() {
+        let products = vec![
+            Product::test("1").with_categories(&["Roupas", "Camisetas", "Manga longa"]),
+            Product::test("2").with_categories(&["Sapatos"]),
+            Product::test("3").with_categories(&["Roupas", "Calças"]),
+            Product::test("4").with_categories(&[]),
+        ];
+        let tree = CategoryTree::from_products(&products);
+        assert_eq!(Some(3), tree.id_for(&products[0].categories));
+        assert_eq!(Some(5), tree.id_for(&products[2].categories));
+        assert_eq!(None, tree.id_for(&products[3].categories));
+        assert_eq!(
+            Some("/roupas/camisetas/manga-longa".to_owned()),
+            tree.url_path(&products[0].categories)
+        );
+        let categories = tree
+            .into_categories()
+            .into_iter()
+            .map(|c| (c.id, c.parent_id, c.slug, c.position))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (1, None, "roupas".to_owned(), 1),
+                (2, Some(1), "camisetas".to_owned(), 1),
+                (3, Some(2), "manga-longa".to_owned(), 1),
+                (4, None, "sapatos".to_owned(), 2),
+                (5, Some(1), "calcas".to_owned(), 2),
+            ],
+            categories
+        );
+    }
+
+    #[test]
+    fn reads_maps_in_utf8_or_windows_1252() {
+        let text = "iluria_category,target_category\nRoupas > Calças,Vestuário > Calças\n";
+        let windows_1252 = encoding_rs::WINDOWS_1252.encode(text).0;
+        assert_eq!(text, decode_map(text.as_bytes()));
+        assert_eq!(
+            text,
+            decode_map(&[b"\xef\xbb\xbf", text.as_bytes()].concat())
+        );
+        assert_eq!(text, decode_map(&windows_1252));
+    }
+
+    #[test]
+    fn maps_exact_categories_ignoring_case() {
+        let mapping = mapping("Roupas > Camisetas", "Vestuário > Camisetas");
+        assert_eq!(
+            Some("Vestuário > Camisetas".to_owned()),
+            mapping.target_for("roupas > camisetas")
+        );
+        assert_eq!(None, mapping.target_for("Roupas"));
+    }
+
+    #[test]
+    fn maps_wildcards_replacing_them_in_the_target() {
+        let clothes_mapping = mapping("Roupas > *", "Vestuário > *");
+        assert_eq!(
+            Some("Vestuário > Calças".to_owned()),
+            clothes_mapping.target_for("Roupas > Calças")
+        );
+        assert_eq!(None, clothes_mapping.target_for("Sapatos > Tênis"));
+        let promotions_mapping = mapping("*Promo*", "Ofertas");
+        assert_eq!(
+            Some("Ofertas".to_owned()),
+            promotions_mapping.target_for("Promoções > Verão")
+        );
+    }
+}
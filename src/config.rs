@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use std::{fs, path::PathBuf};
+
+// Values that can be set in the config file, at the top level for every run,
+// or in a [profile.<name>] table, which overrides the top level ones when the
+// profile is selected with --profile.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub url: Option<String>,
+    pub source: Option<String>,
+    pub output: Option<String>,
+    pub products_file: Option<String>,
+    pub variations_file: Option<String>,
+    pub categories_file: Option<String>,
+    pub force: Option<bool>,
+    pub simulate: Option<bool>,
+    pub fixtures: Option<String>,
+    pub limit: Option<usize>,
+    pub timeout: Option<u64>,
+    pub locale: Option<String>,
+    pub price_rules: Option<String>,
+    pub category_map: Option<String>,
+    pub id_map: Option<String>,
+    pub redirects: Option<bool>,
+    pub picture_columns: Option<usize>,
+    pub pictures_separator: Option<String>,
+    pub lint_rules: Option<String>,
+    pub shipping_defaults: Option<String>,
+    pub description_rules: Option<String>,
+    pub description_format: Option<String>,
+    pub pictures_dir: Option<String>,
+    pub pictures_url: Option<String>,
+    pub new_url: Option<String>,
+    pub strict: Option<bool>,
+}
+
+impl Settings {
+    fn override_with(self, other: Settings) -> Settings {
+        Settings {
+            url: other.url.or(self.url),
+            source: other.source.or(self.source),
+            output: other.output.or(self.output),
+            products_file: other.products_file.or(self.products_file),
+            variations_file: other.variations_file.or(self.variations_file),
+            categories_file: other.categories_file.or(self.categories_file),
+            force: other.force.or(self.force),
+            simulate: other.simulate.or(self.simulate),
+            fixtures: other.fixtures.or(self.fixtures),
+            limit: other.limit.or(self.limit),
+            timeout: other.timeout.or(self.timeout),
+            locale: other.locale.or(self.locale),
+            price_rules: other.price_rules.or(self.price_rules),
+            category_map: other.category_map.or(self.category_map),
+            id_map: other.id_map.or(self.id_map),
+            redirects: other.redirects.or(self.redirects),
+            picture_columns: other.picture_columns.or(self.picture_columns),
+            pictures_separator: other.pictures_separator.or(self.pictures_separator),
+            lint_rules: other.lint_rules.or(self.lint_rules),
+            shipping_defaults: other.shipping_defaults.or(self.shipping_defaults),
+            description_rules: other.description_rules.or(self.description_rules),
+            description_format: other.description_format.or(self.description_format),
+            pictures_dir: other.pictures_dir.or(self.pictures_dir),
+            pictures_url: other.pictures_url.or(self.pictures_url),
+            new_url: other.new_url.or(self.new_url),
+            strict: other.strict.or(self.strict),
+        }
+    }
+}
+
+pub fn default_config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("iluria-export").join("config.toml"))
+}
+
+// Without a config file given, the default one is used, if there is one. The
+// file read is returned with its settings.
+pub fn load_settings(
+    file: Option<&str>,
+    default_file: Option<PathBuf>,
+    profile: Option<&str>,
+) -> Result<(Option<PathBuf>, Settings), String> {
+    let file = match file {
+        Some(file) => PathBuf::from(file),
+        None => match default_file {
+            Some(file) if file.is_file() => file,
+            _ if profile.is_some() => {
+                return Err("Profiles need a config file, use --config.".to_owned())
+            }
+            _ => return Ok((None, Settings::default())),
+        },
+    };
+    let contents = fs::read_to_string(&file)
+        .map_err(|e| format!("Error when opening config file '{}': {}", file.display(), e))?;
+    let settings = parse_settings(&contents, profile)
+        .map_err(|e| format!("Could not read config file '{}': {}", file.display(), e))?;
+    Ok((Some(file), settings))
+}
+
+fn parse_settings(contents: &str, profile: Option<&str>) -> Result<Settings, String> {
+    let mut table = match toml::from_str::<toml::Value>(contents).map_err(|e| e.to_string())? {
+        toml::Value::Table(table) => table,
+        _ => return Err("Config should be a table.".to_owned()),
+    };
+    let mut profiles = match table.remove("profile") {
+        Some(profiles) => profiles
+            .try_into::<std::collections::HashMap<String, Settings>>()
+            .map_err(|e| e.to_string())?,
+        None => Default::default(),
+    };
+    let settings: Settings = toml::Value::Table(table)
+        .try_into()
+        .map_err(|e| e.to_string())?;
+    match profile {
+        None => Ok(settings),
+        Some(profile) => match profiles.remove(profile) {
+            Some(profile_settings) => Ok(settings.override_with(profile_settings)),
+            None => Err(format!("Profile '{}' not found.", profile)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const CONFIG: &str = r#"
+        output = "/exports"
+        force = true
+
+        [profile.loja1]
+        url = "https://loja1.com.br"
+        output = "/exports/loja1"
+
+        [profile.loja2]
+        url = "https://loja2.com.br"
+    "#;
+
+    #[test]
+    fn profiles_override_top_level_settings() -> Result<(), String> {
+        let settings = parse_settings(CONFIG, Some("loja1"))?;
+        assert_eq!(Some("https://loja1.com.br".to_owned()), settings.url);
+        assert_eq!(Some("/exports/loja1".to_owned()), settings.output);
+        assert_eq!(Some(true), settings.force);
+        let settings = parse_settings(CONFIG, None)?;
+        assert_eq!(None, settings.url);
+        assert_eq!(Some("/exports".to_owned()), settings.output);
+        assert_eq!(
+            "Profile 'loja3' not found.",
+            parse_settings(CONFIG, Some("loja3")).unwrap_err()
+        );
+        assert!(parse_settings("outpt = \"/exports\"", None).is_err());
+        Ok(())
+    }
+}
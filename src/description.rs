@@ -0,0 +1,533 @@
+use crate::{report::escape_html, run::Product};
+use scraper::{ElementRef, Html, Node};
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, fs, str::FromStr};
+
+const VOID_TAGS: &[&str] = &["br", "hr", "img"];
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "ul",
+    "ol",
+    "li",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "table",
+    "thead",
+    "tbody",
+    "tr",
+    "th",
+    "td",
+    "hr",
+    "br",
+];
+// Removed with their contents, other tags that are not allowed keep them.
+const DROPPED_TAGS: &[&str] = &["script", "style", "noscript"];
+const ATTRIBUTES: &[&str] = &["href", "src", "alt", "title", "colspan", "rowspan"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DescriptionFormat {
+    #[default]
+    Html,
+    Markdown,
+    Text,
+}
+
+impl FromStr for DescriptionFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "html" => Ok(DescriptionFormat::Html),
+            "markdown" | "md" => Ok(DescriptionFormat::Markdown),
+            "text" | "txt" => Ok(DescriptionFormat::Text),
+            _ => Err(format!(
+                "Unknown description format '{}', use one of: html, markdown, text.",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DescriptionFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptionFormat::Html => write!(f, "html"),
+            DescriptionFormat::Markdown => write!(f, "markdown"),
+            DescriptionFormat::Text => write!(f, "text"),
+        }
+    }
+}
+
+// How descriptions are cleaned, from a TOML file like:
+// allowed-tags = ["p", "br", "b", "i", "ul", "li", "a", "img"]
+// strip-styles = false
+// old-domains = ["minhaloja.iluria.com.br"]
+// move-images = true
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DescriptionRules {
+    allowed_tags: Vec<String>,
+    strip_styles: bool,
+    old_domains: Vec<String>,
+    move_images: bool,
+}
+
+impl Default for DescriptionRules {
+    fn default() -> Self {
+        DescriptionRules {
+            allowed_tags: [
+                "p",
+                "br",
+                "div",
+                "b",
+                "strong",
+                "i",
+                "em",
+                "u",
+                "s",
+                "ul",
+                "ol",
+                "li",
+                "h1",
+                "h2",
+                "h3",
+                "h4",
+                "h5",
+                "h6",
+                "blockquote",
+                "a",
+                "img",
+                "table",
+                "thead",
+                "tbody",
+                "tr",
+                "th",
+                "td",
+                "hr",
+            ]
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect(),
+            strip_styles: true,
+            old_domains: vec![],
+            move_images: false,
+        }
+    }
+}
+
+pub fn load_rules(file: &str) -> Result<DescriptionRules, String> {
+    let contents = fs::read_to_string(file).map_err(|e| {
+        format!(
+            "Error when opening description rules file '{}': {}",
+            file, e
+        )
+    })?;
+    let rules = toml::from_str(&contents)
+        .map_err(|e| format!("Could not read description rules file '{}': {}", file, e))?;
+    printlnv!("Description rules: {:?}", rules);
+    Ok(rules)
+}
+
+// Links of the old store, at the store url or at one of the old domains, are
+// moved to the new url, or to the root of the store without one, and links to
+// products and categories go to their new paths. Images that were downloaded
+// go to where they are hosted now, the others keep their whole address, as
+// they are not at the new store unless move-images says so. Relative
+// addresses are relative to the product page.
+struct Rewriter<'a> {
+    page_url: url::Url,
+    old_hosts: &'a [String],
+    new_url: Option<&'a str>,
+    move_images: bool,
+    new_paths: &'a HashMap<String, String>,
+    new_images: &'a HashMap<String, String>,
+}
+
+impl Rewriter<'_> {
+    // None for addresses that are not pages, images, emails or phones, like
+    // javascript:, which are removed.
+    fn rewrite(&self, url: &str, is_link: bool) -> Option<String> {
+        let url = url.trim();
+        if url.starts_with('#') {
+            return Some(url.to_owned());
+        }
+        let parsed = self.page_url.join(url).ok()?;
+        match parsed.scheme() {
+            "http" | "https" => {}
+            "mailto" | "tel" if is_link => return Some(url.to_owned()),
+            _ => return None,
+        }
+        if !is_link {
+            if let Some(new_image) = self.new_images.get(parsed.as_str()) {
+                return Some(new_image.clone());
+            }
+        }
+        let is_old_host = parsed
+            .host_str()
+            .is_some_and(|host| self.old_hosts.contains(&without_www(host)));
+        if !(is_link || is_old_host && self.move_images) {
+            return Some(parsed.to_string());
+        }
+        if !is_old_host {
+            return Some(url.to_owned());
+        }
+        let mut path = parsed.path().to_owned();
+        if is_link {
+            if let Some(new_path) = self.new_paths.get(&path) {
+                path = new_path.clone();
+            }
+        }
+        let mut new_url = match self.new_url {
+            Some(new_url) => format!("{}{}", new_url.trim_end_matches('/'), path),
+            None => path,
+        };
+        if let Some(query) = parsed.query() {
+            new_url.push('?');
+            new_url.push_str(query);
+        }
+        if let Some(fragment) = parsed.fragment() {
+            new_url.push('#');
+            new_url.push_str(fragment);
+        }
+        Some(new_url)
+    }
+}
+
+fn without_www(host: &str) -> String {
+    host.trim_start_matches("www.").to_lowercase()
+}
+
+// The new paths have the old path of each product and category page, as in
+// the redirects, and the new images the address of each downloaded image.
+pub fn clean_descriptions(
+    rules: &DescriptionRules,
+    format: DescriptionFormat,
+    store_url: &str,
+    new_store_url: Option<&str>,
+    new_paths: &HashMap<String, String>,
+    new_images: &HashMap<String, String>,
+    products: &mut [Product],
+) -> Result<(), String> {
+    let store_url = url::Url::parse(store_url)
+        .map_err(|e| format!("Invalid store url '{}': {}", store_url, e))?;
+    let mut old_hosts = rules
+        .old_domains
+        .iter()
+        .map(|domain| without_www(domain.trim()))
+        .collect::<Vec<_>>();
+    old_hosts.extend(store_url.host_str().map(without_www));
+    for product in products.iter_mut() {
+        let rewriter = Rewriter {
+            page_url: url::Url::parse(&product.url).unwrap_or_else(|_| store_url.clone()),
+            old_hosts: &old_hosts,
+            new_url: new_store_url,
+            move_images: rules.move_images,
+            new_paths,
+            new_images,
+        };
+        let html = Html::parse_fragment(&product.description);
+        let clean = rules.sanitize(html.root_element(), &rewriter);
+        product.description = match format {
+            DescriptionFormat::Html => clean,
+            DescriptionFormat::Markdown | DescriptionFormat::Text => normalize_lines(&to_text(
+                Html::parse_fragment(&clean).root_element(),
+                format == DescriptionFormat::Markdown,
+            )),
+        };
+    }
+    Ok(())
+}
+
+impl DescriptionRules {
+    fn sanitize(&self, element: ElementRef, rewriter: &Rewriter) -> String {
+        let is_block = |node: Option<ElementRef>| {
+            node.is_none_or(|element| BLOCK_TAGS.contains(&element.value().name()))
+        };
+        let mut html = String::new();
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => {
+                    let text = collapse_whitespace(text);
+                    // Whitespace between blocks is only indentation.
+                    if text.trim().is_empty()
+                        && (is_block(child.prev_sibling().and_then(ElementRef::wrap))
+                            || is_block(child.next_sibling().and_then(ElementRef::wrap)))
+                    {
+                        continue;
+                    }
+                    html.push_str(&escape_html(&text));
+                }
+                Node::Element(e) => {
+                    let name = e.name();
+                    if DROPPED_TAGS.contains(&name) {
+                        continue;
+                    }
+                    let inner = self.sanitize(ElementRef::wrap(child).unwrap(), rewriter);
+                    let inner = if BLOCK_TAGS.contains(&name) {
+                        inner.trim().to_owned()
+                    } else {
+                        inner
+                    };
+                    if !self
+                        .allowed_tags
+                        .iter()
+                        .any(|tag| tag.eq_ignore_ascii_case(name))
+                    {
+                        html.push_str(&inner);
+                        continue;
+                    }
+                    let is_void = VOID_TAGS.contains(&name);
+                    // Empty paragraphs, like <p>&nbsp;</p> or <p><br></p>.
+                    if !is_void
+                        && name != "td"
+                        && name != "th"
+                        && inner.replace("<br>", "").trim().is_empty()
+                    {
+                        continue;
+                    }
+                    html.push('<');
+                    html.push_str(name);
+                    // Sorted, as the order of the attributes of the page is lost.
+                    let mut attributes = e.attrs().collect::<Vec<_>>();
+                    attributes.sort_unstable();
+                    for (attribute, value) in attributes {
+                        let value = match attribute {
+                            "href" | "src" => match rewriter.rewrite(value, attribute == "href") {
+                                Some(value) => value,
+                                None => continue,
+                            },
+                            "style" | "class" if !self.strip_styles => value.to_owned(),
+                            _ if ATTRIBUTES.contains(&attribute) => value.to_owned(),
+                            _ => continue,
+                        };
+                        html.push_str(&format!(" {}=\"{}\"", attribute, escape_html(&value)));
+                    }
+                    html.push('>');
+                    if !is_void {
+                        html.push_str(&inner);
+                        html.push_str(&format!("</{}>", name));
+                    }
+                }
+                _ => {}
+            }
+        }
+        html
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut last_was_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_was_space {
+                collapsed.push(' ');
+            }
+            last_was_space = true;
+        } else {
+            collapsed.push(c);
+            last_was_space = false;
+        }
+    }
+    collapsed
+}
+
+// Markdown, or plain text, from a description already sanitized.
+fn to_text(element: ElementRef, markdown: bool) -> String {
+    let mut text = String::new();
+    for child in element.children() {
+        let e = match child.value() {
+            Node::Text(t) if markdown => {
+                let mut escaped = String::new();
+                for c in collapse_whitespace(t).chars() {
+                    if "\\*_[]`".contains(c) {
+                        escaped.push('\\');
+                    }
+                    escaped.push(c);
+                }
+                text.push_str(&escaped);
+                continue;
+            }
+            Node::Text(t) => {
+                text.push_str(&collapse_whitespace(t));
+                continue;
+            }
+            Node::Element(e) => e,
+            _ => continue,
+        };
+        let inner = to_text(ElementRef::wrap(child).unwrap(), markdown);
+        let attribute = |name: &str| e.attr(name).unwrap_or_default();
+        match e.name() {
+            "br" if markdown => text.push_str("\\\n"),
+            "br" => text.push('\n'),
+            "hr" if markdown => text.push_str("\n\n---\n\n"),
+            "hr" => text.push_str("\n\n"),
+            heading @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+                text.push_str("\n\n");
+                if markdown {
+                    text.push_str(&"#".repeat(usize::from(heading.as_bytes()[1] - b'0')));
+                    text.push(' ');
+                }
+                text.push_str(inner.trim());
+                text.push_str("\n\n");
+            }
+            "li" => {
+                let ordered = child
+                    .parent()
+                    .and_then(ElementRef::wrap)
+                    .is_some_and(|parent| parent.value().name() == "ol");
+                text.push('\n');
+                text.push_str(if markdown && ordered { "1. " } else { "- " });
+                text.push_str(inner.trim());
+            }
+            "tr" => {
+                text.push('\n');
+                text.push_str(inner.trim());
+            }
+            "td" | "th" => {
+                text.push_str(inner.trim());
+                text.push(' ');
+            }
+            "b" | "strong" if markdown && !inner.trim().is_empty() => {
+                text.push_str(&format!("**{}**", inner))
+            }
+            "i" | "em" if markdown && !inner.trim().is_empty() => {
+                text.push_str(&format!("*{}*", inner))
+            }
+            "a" if markdown && !attribute("href").is_empty() => {
+                text.push_str(&format!("[{}]({})", inner.trim(), attribute("href")))
+            }
+            "img" if markdown => {
+                text.push_str(&format!("![{}]({})", attribute("alt"), attribute("src")))
+            }
+            name if BLOCK_TAGS.contains(&name) => {
+                text.push_str("\n\n");
+                text.push_str(&inner);
+                text.push_str("\n\n");
+            }
+            _ => text.push_str(&inner),
+        }
+    }
+    text
+}
+
+// Lines without the spaces around them, and at most one blank line between
+// blocks.
+fn normalize_lines(text: &str) -> String {
+    let mut lines: Vec<&str> = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const DESCRIPTION: &str = r#"
+        <div class="iluria-text" style="color: red">
+          <p style="font-size: 14px">Camiseta   de <b>algodão</b>.&nbsp;</p>
+          <p>&nbsp;</p>
+          <p><br></p>
+          <script>alert('oi')</script>
+          <p><span class="x">Veja a <a href="/pd-2" target="_blank">caneca</a></span> e
+             <a href="https://outraloja.com.br/pd-2">outra</a>.
+             <a href=" javascript:alert('oi')">Clique</a></p>
+          <ul><li>Tamanhos <i>P</i> e <i>M</i></li><li>100% algodão</li></ul>
+          <img src="http://www.loja.com.br/imagens/camiseta.jpg" alt="Camiseta" width="500">
+          <img src="imagens/tabela.png">
+        </div>"#;
+
+    fn clean(
+        rules: &str,
+        new_store_url: Option<&str>,
+        format: DescriptionFormat,
+    ) -> Result<String, String> {
+        let rules: DescriptionRules = toml::from_str(rules).unwrap();
+        let new_paths = [("/pd-2".to_owned(), "/caneca".to_owned())]
+            .iter()
+            .cloned()
+            .collect();
+        let new_images = [(
+            "http://loja.com.br/imagens/tabela.png".to_owned(),
+            "https://fotos.com.br/tabela.png".to_owned(),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        let mut products = vec![Product {
+            description: DESCRIPTION.to_owned(),
+            ..Default::default()
+        }];
+        clean_descriptions(
+            &rules,
+            format,
+            "http://loja.com.br",
+            new_store_url,
+            &new_paths,
+            &new_images,
+            &mut products,
+        )?;
+        Ok(products.remove(0).description)
+    }
+
+    #[test]
+    fn cleans_descriptions_and_rewrites_links_to_the_old_store() -> Result<(), String> {
+        assert_eq!(
+            "<div><p>Camiseta de <b>algodão</b>.</p>\
+             <p>Veja a <a href=\"https://nova.com.br/caneca\">caneca</a> e \
+             <a href=\"https://outraloja.com.br/pd-2\">outra</a>. <a>Clique</a></p>\
+             <ul><li>Tamanhos <i>P</i> e <i>M</i></li><li>100% algodão</li></ul>\
+             <img alt=\"Camiseta\" src=\"https://nova.com.br/imagens/camiseta.jpg\"> \
+             <img src=\"https://fotos.com.br/tabela.png\"></div>",
+            clean(
+                "move-images = true",
+                Some("https://nova.com.br/"),
+                DescriptionFormat::Html
+            )?
+        );
+        assert_eq!(
+            "<p>Camiseta de algodão.</p>\
+             <p>Veja a caneca e outra. Clique</p>\
+             Tamanhos P e M100% algodão",
+            clean("allowed-tags = [\"p\"]", None, DescriptionFormat::Html)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn writes_descriptions_as_markdown_or_text() -> Result<(), String> {
+        assert_eq!(
+            "Camiseta de **algodão**.\n\
+             \n\
+             Veja a [caneca](/caneca) e [outra](https://outraloja.com.br/pd-2). Clique\n\
+             \n\
+             - Tamanhos *P* e *M*\n\
+             - 100% algodão\n\
+             \n\
+             ![Camiseta](http://www.loja.com.br/imagens/camiseta.jpg) \
+             ![](https://fotos.com.br/tabela.png)",
+            clean("", None, DescriptionFormat::Markdown)?
+        );
+        assert_eq!(
+            "Camiseta de algodão.\n\
+             \n\
+             Veja a caneca e outra. Clique\n\
+             \n\
+             - Tamanhos P e M\n\
+             - 100% algodão",
+            clean("", None, DescriptionFormat::Text)?
+        );
+        Ok(())
+    }
+}
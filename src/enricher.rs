@@ -1,264 +1,188 @@
-use std::collections::HashMap;
-
+use crate::ids;
+use crate::money::{self, Locale};
 use crate::progressbar;
-use scraper::{Html, Selector};
+use crate::run::{Availability, Product, Variation};
+use crate::sources::ProductSource;
+use std::time::Duration;
 
+// When simulating, the pages come from the source's simulation instead of the
+// store, and go through the same parsing.
 pub fn enrich_products(
     base_url: &str,
-    products: &mut Vec<crate::run::Product>,
+    products: &mut [Product],
+    source: &dyn ProductSource,
     simulate: bool,
+    fixtures_dir: Option<&str>,
+    timeout: Duration,
+    locale: Locale,
 ) -> Result<(), String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| format!("Could not create the client to get pages: {}", e))?;
     for product in products.iter_mut() {
-        let url = format!("{}/pd-{}", base_url, product.id);
+        let url = source.product_url(base_url, product)?;
+        product.url = url.clone();
+        let report_variations = std::mem::take(&mut product.variations);
         progressbar::inc_progress_bar(1);
-        if simulate {
+        let body = if simulate {
             printlnv!("Simulating web request at: {}", url);
-            std::thread::sleep(std::time::Duration::from_millis(300));
-            continue;
+            Some(source.simulated_page(product, fixtures_dir, locale)?)
         } else {
             printlnv!("Making web request at: {}", url);
-        }
-        let client = reqwest::blocking::Client::new();
-        let resp = client
-            .get(&url)
-            .header("user-agent", "Mozilla/5.0")
-            .send()
-            .map_err(|e| format!("Could not get at {}. Details: {}", url, e))?;
-        if !resp.status().is_success() {
-            return Err(format!(
-                "Request for product {} failed with status code {}",
+            get_page(&client, &url, product, timeout)?
+        };
+        let body = match body {
+            Some(body) => body,
+            None => {
+                printlnpb!(
+                    "Warning: product {} '{}' was not found at {}, it will be exported as inactive.",
+                    product.id,
+                    product.name,
+                    url
+                );
+                product.availability = Availability::NotFound;
+                continue;
+            }
+        };
+        source.enrich_product(product, &body, locale)?;
+        add_report_codes(product, &report_variations);
+        if product.availability == Availability::Unavailable {
+            printlnpb!(
+                "Warning: product {} '{}' is unavailable, it will be exported as inactive.",
                 product.id,
-                resp.status()
-            ));
+                product.name
+            );
+        }
+        for variation in product.variations.iter() {
+            if variation.price != product.price {
+                printlnv!(
+                    "Variation '{}' of product {} costs {} instead of {}.",
+                    variation.name1,
+                    product.id,
+                    money::format(variation.price, locale),
+                    money::format(product.price, locale)
+                );
+            }
         }
-        let body = resp
-            .text()
-            .map_err(|e| format!("Could not get body: {}", e))?;
-        let fragment = Html::parse_document(&body);
-        product.description = get_description(&fragment, &product.id)?;
-        let (category, subcategory) = get_category(&fragment, &product.id)?;
-        product.category = category;
-        product.subcategory = subcategory;
-        product.pictures = get_pictures(&fragment, &product.id)?;
-        product.variations = get_variations(&fragment, &product.id)?
-            .into_iter()
-            .map(|v| crate::run::Variation {
-                type1: v.type1,
-                type2: v.type2,
-                type3: v.type3,
-                name1: v.name1,
-                name2: v.name2,
-                name3: v.name3,
-                picture: v.picture,
-                price: v.price,
-            })
-            .collect();
         printlnv!("Enriched product: {:?}", product);
     }
     Ok(())
 }
 
-fn get_variations(fragment: &Html, product_id: &str) -> Result<Vec<VariationWithId>, String> {
-    let variations_selector = Selector::parse("input.allVariations").map_err(|e| {
-        format!(
-            "Could not get variations for product {}: {:?}",
-            product_id, e
-        )
-    })?;
-    let variations_select = fragment.select(&variations_selector);
-    let mut variations = variations_select
-        .map(|e| e.value())
-        .map(|e| {
-            printlnv!("Found variation input: {:?}", e);
-            VariationWithId {
-                type1: "".to_owned(),
-                type2: None,
-                type3: None,
-                name1: "".to_owned(),
-                name2: None,
-                name3: None,
-                id1: if let Some(v) = e.attr("value1") {
-                    v.trim()
-                } else {
-                    ""
-                }
-                .to_owned(),
-                id2: if let Some(v) = e.attr("value2") {
-                    let trimmed = v.trim();
-                    if trimmed.is_empty() {
-                        None
-                    } else {
-                        Some(trimmed.to_owned())
-                    }
-                } else {
-                    None
-                },
-                id3: if let Some(v) = e.attr("value3") {
-                    let trimmed = v.trim();
-                    if trimmed.is_empty() {
-                        None
-                    } else {
-                        Some(trimmed.to_owned())
-                    }
-                } else {
-                    None
-                },
-                price: e
-                    .attr("convertedprice")
-                    .unwrap_or("R$ 0,00")
-                    .replace("R$ ", "")
-                    .replace(".", "")
-                    .replace(",", ".")
-                    .parse::<f64>()
-                    .unwrap(),
-                picture: e.attr("mainpictureurl550").map(|v| get_picture_url(v)),
+// Codes of the report win over the ones of the page. Its variations are found
+// by their options, in any order.
+fn add_report_codes(product: &mut Product, report_variations: &[Variation]) {
+    for report_variation in report_variations {
+        let options = ids::variation_options(&[&report_variation.name1]);
+        let variation = product.variations.iter_mut().find(|variation| {
+            ids::variation_options(&[
+                &variation.name1,
+                variation.name2.as_deref().unwrap_or_default(),
+                variation.name3.as_deref().unwrap_or_default(),
+            ]) == options
+        });
+        let variation = match variation {
+            Some(variation) => variation,
+            None => {
+                printlnpb!(
+                    "Warning: variation '{}' of product {} was not found at its page, its SKU and GTIN of the report are not exported.",
+                    report_variation.name1,
+                    product.id
+                );
+                continue;
             }
-        })
-        .collect::<Vec<_>>();
-
-    let mut variation_ids_1 = HashMap::new();
-    for id in variations
-        .iter()
-        .map(|v| &v.id1)
-        .filter(|id| !id.is_empty())
-    {
-        variation_ids_1.insert(id.clone(), "".to_owned());
-    }
-    let mut variation_ids_2 = HashMap::new();
-    let mut variation_ids_3 = HashMap::new();
-    for id in variations.iter().map(|v| v.id2.as_ref()).filter_map(|v| v) {
-        variation_ids_2.insert(id.clone(), "".to_owned());
-    }
-    for id in variations.iter().map(|v| v.id3.as_ref()).filter_map(|v| v) {
-        variation_ids_3.insert(id.clone(), "".to_owned());
-    }
-
-    let variation_type_1 =
-        get_text_from_selector(fragment, "#iluria-product-variation1 > option[value='0']")?;
-    let variation_type_2 =
-        get_text_from_selector(fragment, "#iluria-product-variation2 > option[value='0']")?;
-    let variation_type_3 =
-        get_text_from_selector(fragment, "#iluria-product-variation3 > option[value='0']")?;
-
-    for (id, value) in variation_ids_1.iter_mut() {
-        *value = if let Some(value) = get_text_from_selector(
-            fragment,
-            &format!("#iluria-product-variation1 > option[value='{}']", id),
-        )? {
-            value
-        } else {
-            return Err(format!("Could not find variation value for id '{}'.", id));
-        }
-    }
-    for (id, value) in variation_ids_2.iter_mut() {
-        *value = if let Some(value) = get_text_from_selector(
-            fragment,
-            &format!("#iluria-product-variation2 > option[value='{}']", id),
-        )? {
-            value
-        } else {
-            return Err(format!("Could not find variation value for id '{}'.", id));
-        }
-    }
-    for (id, value) in variation_ids_3.iter_mut() {
-        *value = if let Some(value) = get_text_from_selector(
-            fragment,
-            &format!("#iluria-product-variation3 > option[value='{}']", id),
-        )? {
-            value
-        } else {
-            return Err(format!("Could not find variation value for id '{}'.", id));
+        };
+        if !report_variation.sku.is_empty() {
+            variation.sku = report_variation.sku.clone();
         }
-    }
-    for variation in variations.iter_mut() {
-        variation.type1 = variation_type_1.clone().unwrap();
-        variation.type2 = variation_type_2.clone();
-        variation.type3 = variation_type_3.clone();
-        variation.name1 = variation_ids_1[&variation.id1].clone();
-        if let Some(v) = &variation.id2 {
-            variation.name2 = Some(variation_ids_2[v].clone());
-        }
-        if let Some(v) = &variation.id3 {
-            variation.name3 = Some(variation_ids_3[v].clone());
+        if !report_variation.gtin.is_empty() {
+            variation.gtin = report_variation.gtin.clone();
         }
     }
-    Ok(variations)
 }
 
-fn get_text_from_selector(fragment: &Html, selector: &str) -> Result<Option<String>, String> {
-    let variation1_selector = Selector::parse(selector)
-        .map_err(|e| format!("Could not get value for selector {}: {:?}", selector, e))?;
-    let mut select = fragment.select(&variation1_selector);
-    Ok(if let Some(d) = select.next() {
-        Some(d.text().collect::<String>().trim().to_owned())
-    } else {
-        None
-    })
-}
-
-fn get_description(fragment: &Html, product_id: &str) -> Result<String, String> {
-    let description_selector =
-        Selector::parse("div:not([id]).product-description").map_err(|e| {
+// Pages that are not found are None, as products deleted from the store are
+// still in the stock report. A store that stops answering stops the export,
+// instead of leaving it waiting forever.
+fn get_page(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    product: &Product,
+    timeout: Duration,
+) -> Result<Option<String>, String> {
+    let error = |e: reqwest::Error| {
+        if e.is_timeout() {
             format!(
-                "Could not get description for product {}: {:?}",
-                product_id, e
+                "The page of product {} at {} took more than {} seconds, try again or use a longer --timeout.",
+                product.id,
+                url,
+                timeout.as_secs()
             )
-        })?;
-    Ok(
-        if let Some(d) = fragment.select(&description_selector).next() {
-            d.inner_html()
         } else {
-            "".to_owned()
-        },
-    )
-}
-
-fn get_category(fragment: &Html, product_id: &str) -> Result<(String, String), String> {
-    let category_selector = Selector::parse(".breadcrumb a")
-        .map_err(|e| format!("Could not get category for product {}: {:?}", product_id, e))?;
-    let category_and_subcategory: Vec<_> = fragment.select(&category_selector).skip(2).collect();
-    let category = if !category_and_subcategory.is_empty() {
-        category_and_subcategory[0].text().collect()
-    } else {
-        "".to_owned()
-    };
-    let subcategory = if category_and_subcategory.len() == 2 {
-        category_and_subcategory[1].text().collect()
-    } else {
-        "".to_owned()
+            format!("Could not get at {}. Details: {}", url, e)
+        }
     };
-    Ok((category, subcategory))
+    let resp = client
+        .get(url)
+        .header("user-agent", "Mozilla/5.0")
+        .send()
+        .map_err(error)?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        return Err(format!(
+            "Request for product {} failed with status code {}",
+            product.id,
+            resp.status()
+        ));
+    }
+    resp.text().map(Some).map_err(error)
 }
 
-fn get_pictures(fragment: &Html, product_id: &str) -> Result<Vec<String>, String> {
-    let images_selector = Selector::parse("#thumbsContainer img")
-        .map_err(|e| format!("Could not get images for product {}: {:?}", product_id, e))?;
-    let pictures = fragment
-        .select(&images_selector)
-        .filter_map(|i| i.value().attr("mainpictureurl"))
-        .map(|s| get_picture_url(s))
-        .collect();
-    Ok(pictures)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
 
-fn get_picture_url(url: &str) -> String {
-    if url.starts_with('/') {
-        format!("http:{}", url)
-    } else {
-        url.to_owned()
+    #[test]
+    fn matches_report_variations_by_their_options_in_any_order() {
+        let mut product = Product {
+            variations: vec![
+                Variation {
+                    name1: "P".to_owned(),
+                    name2: Some("Azul".to_owned()),
+                    ..Default::default()
+                },
+                Variation {
+                    name1: "G".to_owned(),
+                    name2: Some("Azul".to_owned()),
+                    sku: "PAGINA-G".to_owned(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let report_variation = |name: &str, sku: &str| Variation {
+            name1: name.to_owned(),
+            sku: sku.to_owned(),
+            ..Default::default()
+        };
+        add_report_codes(
+            &mut product,
+            &[
+                report_variation("Azul / P", "RELATORIO-P"),
+                report_variation("Verde / P", "RELATORIO-VERDE"),
+            ],
+        );
+        assert_eq!(
+            vec!["RELATORIO-P", "PAGINA-G"],
+            product
+                .variations
+                .iter()
+                .map(|v| v.sku.as_str())
+                .collect::<Vec<_>>()
+        );
     }
 }
-
-struct VariationWithId {
-    id1: String,
-    id2: Option<String>,
-    id3: Option<String>,
-    type1: String,
-    type2: Option<String>,
-    type3: Option<String>,
-    name1: String,
-    name2: Option<String>,
-    name3: Option<String>,
-    price: f64,
-    picture: Option<String>,
-}
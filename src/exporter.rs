@@ -1,108 +1,399 @@
+use crate::categories::{self, CategoryTree};
+use crate::ids::{self, IdMap, IdMapping};
+use crate::lint::Problem;
+use crate::money::Money;
+use crate::redirects::{self, Redirect};
+use crate::report::RunReport;
+use crate::run::Product;
+use crate::stock::StockUpdate;
+use crate::sync::CatalogChanges;
 use serde::Serialize;
 use std::fs;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum PictureLayout {
+    Columns(usize),
+    AllColumns(usize),
+    Delimited(String),
+}
+
+impl PictureLayout {
+    // With all columns, the number of columns is the number of pictures of the
+    // product that has the most of them.
+    pub fn for_products(
+        columns: Option<usize>,
+        separator: Option<String>,
+        products: &[Product],
+    ) -> PictureLayout {
+        match (columns, separator) {
+            (_, Some(separator)) => PictureLayout::Delimited(separator),
+            (Some(0), None) => PictureLayout::AllColumns(
+                products.iter().map(|p| p.pictures.len()).max().unwrap_or(0),
+            ),
+            (Some(columns), None) => PictureLayout::Columns(columns),
+            (None, None) => PictureLayout::Columns(5),
+        }
+    }
+}
+
+fn picture_columns(product: &Product, picture_layout: &PictureLayout) -> Vec<String> {
+    match picture_layout {
+        PictureLayout::Delimited(separator) => vec![product.pictures.join(separator)],
+        PictureLayout::Columns(columns) | PictureLayout::AllColumns(columns) => {
+            if product.pictures.len() > *columns {
+                printlnpb!(
+                    "Warning: product {} '{}' has {} pictures, only the first {} were exported.",
+                    product.id,
+                    product.name,
+                    product.pictures.len(),
+                    columns
+                );
+            }
+            (0..*columns)
+                .map(|i| product.pictures.get(i).cloned().unwrap_or_default())
+                .collect()
+        }
+    }
+}
+
+pub struct ExportContext<'a> {
+    pub category_tree: &'a CategoryTree,
+    pub id_map: &'a IdMap,
+    pub picture_layout: &'a PictureLayout,
+    // Address of the new store, from --new-url.
+    pub new_store_url: Option<&'a str>,
+}
+
+impl ExportContext<'_> {
+    fn export_id(&self, product: &Product) -> String {
+        self.id_map
+            .export_id(&product.id)
+            .expect("All products should have an export id.")
+            .to_owned()
+    }
+
+    // Search engines are told the page of the new store, not the old one,
+    // which is going to be shut down. Canonical urls are absolute, so there is
+    // none without the address of the new store.
+    fn canonical_url(&self, product: &Product) -> String {
+        match self.new_store_url {
+            Some(new_store_url) => {
+                format!("{}/{}", new_store_url.trim_end_matches('/'), product.slug)
+            }
+            None => "".to_owned(),
+        }
+    }
+}
+
 pub fn save_enriched_products_to_file(
-    products: Vec<crate::run::Product>,
+    products: &[Product],
+    context: &ExportContext,
     products_file: Option<String>,
     variations_file: Option<String>,
 ) -> Result<(), String> {
-    let mut i: u32 = 0;
-    let (product_export, variation_export) = products
-        .into_iter()
+    save_products_to_file(products, context, products_file)?;
+    save_variations_to_file(products, context, variations_file)
+}
+
+pub fn save_products_to_file(
+    products: &[Product],
+    context: &ExportContext,
+    products_file: Option<String>,
+) -> Result<(), String> {
+    let product_export = products
+        .iter()
         .map(|p| {
-            i += 1;
-            let len = p.pictures.len();
-            let mut pics = p.pictures;
-            let picture1 = if len > 0 {
-                pics.remove(0)
-            } else {
-                "".to_owned()
-            };
-            let picture2 = if len > 0 {
-                pics.remove(0)
-            } else {
-                "".to_owned()
-            };
-            let picture3 = if len > 0 {
-                pics.remove(0)
-            } else {
-                "".to_owned()
-            };
-            let picture4 = if len > 0 {
-                pics.remove(0)
-            } else {
-                "".to_owned()
-            };
-            let picture5 = if len > 0 {
-                pics.remove(0)
-            } else {
-                "".to_owned()
-            };
             (
                 ProductCsvExport {
-                    id: i.to_string(),
-                    active: "Sim".to_owned(),
-                    name: p.name,
+                    id: context.export_id(p),
+                    active: if p.is_active() { "Sim" } else { "Não" }.to_owned(),
+                    slug: p.slug.clone(),
+                    name: p.name.clone(),
                     stock: p.stock,
                     price: p.price,
+                    promotional_price: p.promotion.as_ref().map(|promotion| promotion.price),
+                    promotion_start: p
+                        .promotion
+                        .as_ref()
+                        .and_then(|promotion| promotion.start.clone()),
+                    promotion_end: p
+                        .promotion
+                        .as_ref()
+                        .and_then(|promotion| promotion.end.clone()),
+                    installments: p
+                        .installments
+                        .as_ref()
+                        .map(|installments| installments.count),
+                    installment_value: p
+                        .installments
+                        .as_ref()
+                        .map(|installments| installments.value),
+                    interest_free: p.installments.as_ref().map(|installments| {
+                        if installments.interest_free {
+                            "Sim"
+                        } else {
+                            "Não"
+                        }
+                        .to_owned()
+                    }),
                     price_cost: p.price_cost,
-                    vendor_name: p.vendor_name,
+                    vendor_name: p.vendor_name.clone(),
                     description: p.description.trim().to_owned(),
-                    category: p.category,
-                    subcategory: p.subcategory,
-                    picture1,
-                    picture2,
-                    picture3,
-                    picture4,
-                    picture5,
+                    category_id: context.category_tree.id_for(&p.categories),
+                    category: p.categories.first().cloned().unwrap_or_default(),
+                    subcategory: p.categories[p.categories.len().min(1)..]
+                        .join(categories::PATH_SEPARATOR),
+                    weight: p.shipping.weight,
+                    height: p.shipping.height,
+                    width: p.shipping.width,
+                    length: p.shipping.length,
+                    seo_title: p.seo.title.clone(),
+                    seo_description: p.seo.description.clone(),
+                    seo_keywords: p.seo.keywords.clone(),
+                    canonical_url: context.canonical_url(p),
+                    old_url: if p.seo.canonical_url.is_empty() {
+                        p.url.clone()
+                    } else {
+                        p.seo.canonical_url.clone()
+                    },
+                    sku: p.sku.clone(),
+                    gtin: p.gtin.clone(),
+                    brand: p.brand.clone(),
                 },
-                p.variations
-                    .into_iter()
-                    .map(|v| VariationCsvExport {
-                        id: "".to_owned(),
-                        product_id: i.to_string(),
-                        type1: v.type1,
-                        name1: v.name1,
-                        type2: v.type2,
-                        name2: v.name2,
-                        type3: v.type3,
-                        name3: v.name3,
-                        price: v.price,
-                        picture: v.picture,
-                    })
-                    .collect::<Vec<VariationCsvExport>>(),
+                picture_columns(p, context.picture_layout),
             )
         })
-        .fold((vec![], vec![]), |(mut ps, mut vss), (p, mut vs)| {
-            ps.push(p);
-            vss.append(&mut vs);
-            (ps, vss)
-        });
-    let mut product_wtr = csv::Writer::from_writer(vec![]);
-    for p in product_export.into_iter() {
-        product_wtr
-            .serialize(&p)
-            .map_err(|e| format!("Could not serialize product {:?}. Details: {}", &p, e))?;
-    }
-    let product_text = String::from_utf8(product_wtr.into_inner().map_err(|e| e.to_string())?)
-        .map_err(|e| e.to_string())?;
-    match products_file {
-        None => printlnpb!("Products:\n{}", product_text),
-        Some(file) => fs::write(&file, encode_ansi(product_text))
-            .map_err(|e| format!("Error when writing products file '{}': {}", file, e))?,
-    }
-    let mut variation_wtr = csv::Writer::from_writer(vec![]);
-    for v in variation_export.into_iter() {
-        variation_wtr
-            .serialize(&v)
-            .map_err(|e| format!("Could not serialize variation {:?}. Details: {}", &v, e))?;
-    }
-    let variation_text = String::from_utf8(variation_wtr.into_inner().map_err(|e| e.to_string())?)
+        .collect::<Vec<_>>();
+    let picture_headers = match context.picture_layout {
+        PictureLayout::Delimited(_) => vec!["pictures".to_owned()],
+        _ => (1..=product_export.first().map_or(0, |p| p.1.len()))
+            .map(|i| format!("picture{}", i))
+            .collect(),
+    };
+    write_csv_with_extra_columns(
+        product_export,
+        picture_headers,
+        "Products",
+        "product",
+        products_file,
+    )
+}
+
+pub fn save_variations_to_file(
+    products: &[Product],
+    context: &ExportContext,
+    variations_file: Option<String>,
+) -> Result<(), String> {
+    let variation_export = products
+        .iter()
+        .flat_map(|p| {
+            let product_id = context.export_id(p);
+            p.variations.iter().map(move |v| VariationCsvExport {
+                id: ids::page_variation_id(&product_id, v),
+                product_id: product_id.clone(),
+                type1: v.type1.clone(),
+                name1: v.name1.clone(),
+                type2: v.type2.clone(),
+                name2: v.name2.clone(),
+                type3: v.type3.clone(),
+                name3: v.name3.clone(),
+                price: v.price,
+                promotional_price: v.promotional_price,
+                sku: v.sku.clone(),
+                gtin: v.gtin.clone(),
+                picture: v.picture.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+    write_csv(variation_export, "Variations", "variation", variations_file)
+}
+
+pub fn save_changes_to_files(
+    changes: &CatalogChanges,
+    context: &ExportContext,
+    create_files: (Option<String>, Option<String>),
+    update_files: (Option<String>, Option<String>),
+    delete_files: (Option<String>, Option<String>),
+    changes_file: Option<String>,
+) -> Result<(), String> {
+    save_products_to_file(&changes.created, context, create_files.0)?;
+    save_variations_to_file(
+        &[&changes.created[..], &changes.variations_created[..]].concat(),
+        context,
+        create_files.1,
+    )?;
+    save_products_to_file(&changes.updated, context, update_files.0)?;
+    save_variations_to_file(&changes.variations_updated, context, update_files.1)?;
+    save_products_to_file(&changes.deleted, context, delete_files.0)?;
+    save_variations_to_file(&changes.variations_deleted, context, delete_files.1)?;
+    write_text(changes.summary(), "Changes", changes_file)
+}
+
+// Without an output directory only the products and variations, or the stock
+// updates, are printed to the screen. The other files, like the catalog, which
+// is only useful for the next run, are only written to the output directory.
+pub fn save_catalog_to_file(catalog: String, catalog_file: Option<String>) -> Result<(), String> {
+    match catalog_file {
+        None => Ok(()),
+        file => write_text(catalog, "Catalog", file),
+    }
+}
+
+pub fn save_categories_to_file(
+    category_tree: CategoryTree,
+    categories_file: Option<String>,
+) -> Result<(), String> {
+    match categories_file {
+        None => Ok(()),
+        file => write_csv(
+            category_tree.into_categories(),
+            "Categories",
+            "category",
+            file,
+        ),
+    }
+}
+
+pub fn save_id_map_to_file(id_map: IdMap, id_map_file: Option<String>) -> Result<(), String> {
+    match id_map_file {
+        None => Ok(()),
+        file => write_csv::<IdMapping>(id_map.into_mappings(), "Ids", "id", file),
+    }
+}
+
+pub fn save_lint_report_to_file(
+    problems: &[Problem],
+    lint_file: Option<String>,
+) -> Result<(), String> {
+    match lint_file {
+        None => Ok(()),
+        file => write_csv(problems.iter().collect(), "Validation", "problem", file),
+    }
+}
+
+pub fn save_run_report_to_files(
+    report: &RunReport,
+    html_file: Option<String>,
+    markdown_file: Option<String>,
+) -> Result<(), String> {
+    if html_file.is_some() {
+        write_text(report.to_html(), "HTML report", html_file)?;
+    }
+    match markdown_file {
+        None => Ok(()),
+        file => write_text(report.to_markdown(), "Report", file),
+    }
+}
+
+pub fn save_stock_updates_to_file(
+    stock_updates: Vec<StockUpdate>,
+    stock_file: Option<String>,
+) -> Result<(), String> {
+    write_csv(stock_updates, "Stock", "stock update", stock_file)
+}
+
+pub fn save_redirects_to_files(
+    redirects: Vec<Redirect>,
+    csv_file: Option<String>,
+    nginx_file: Option<String>,
+    htaccess_file: Option<String>,
+) -> Result<(), String> {
+    write_text(
+        redirects::to_nginx_map(&redirects),
+        "Nginx redirects",
+        nginx_file,
+    )?;
+    write_text(
+        redirects::to_htaccess(&redirects),
+        "Apache redirects",
+        htaccess_file,
+    )?;
+    write_csv(redirects, "Redirects", "redirect", csv_file)
+}
+
+pub fn save_categories_found_to_file(
+    categories_found: Vec<categories::CategoryFound>,
+    categories_found_file: Option<String>,
+) -> Result<(), String> {
+    match categories_found_file {
+        None => Ok(()),
+        file => write_csv(categories_found, "Iluria categories", "category", file),
+    }
+}
+
+fn write_csv<T: Serialize + std::fmt::Debug>(
+    records: Vec<T>,
+    title: &str,
+    record_name: &str,
+    file: Option<String>,
+) -> Result<(), String> {
+    write_csv_with_extra_columns(
+        records.into_iter().map(|r| (r, vec![])).collect(),
+        vec![],
+        title,
+        record_name,
+        file,
+    )
+}
+
+fn write_csv_with_extra_columns<T: Serialize + std::fmt::Debug>(
+    records: Vec<(T, Vec<String>)>,
+    extra_headers: Vec<String>,
+    title: &str,
+    record_name: &str,
+    file: Option<String>,
+) -> Result<(), String> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    if let Some((first, _)) = records.first() {
+        let mut headers = headers_of(first)?;
+        headers.extend(extra_headers);
+        wtr.write_record(&headers).map_err(|e| e.to_string())?;
+    }
+    for record in records.into_iter() {
+        wtr.serialize(&record).map_err(|e| {
+            format!(
+                "Could not serialize {} {:?}. Details: {}",
+                record_name, &record, e
+            )
+        })?;
+    }
+    let text = String::from_utf8(wtr.into_inner().map_err(|e| e.to_string())?)
         .map_err(|e| e.to_string())?;
-    match variations_file {
-        None => printlnpb!("Variations:\n{}", variation_text),
-        Some(file) => fs::write(&file, encode_ansi(variation_text))
-            .map_err(|e| format!("Error when writing variations file '{}': {}", file, e))?,
+    match file {
+        None => printlnpb!("{}:\n{}", title, text),
+        Some(file) => fs::write(&file, encode_ansi(text)).map_err(|e| {
+            format!(
+                "Error when writing {} file '{}': {}",
+                title.to_lowercase(),
+                file,
+                e
+            )
+        })?,
+    }
+    Ok(())
+}
+
+fn headers_of<T: Serialize>(record: &T) -> Result<csv::StringRecord, String> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    wtr.serialize(record).map_err(|e| e.to_string())?;
+    let data = wtr.into_inner().map_err(|e| e.to_string())?;
+    let mut rdr = csv::Reader::from_reader(data.as_slice());
+    Ok(rdr.headers().map_err(|e| e.to_string())?.clone())
+}
+
+fn write_text(text: String, title: &str, file: Option<String>) -> Result<(), String> {
+    match file {
+        None => printlnpb!("{}:\n{}", title, text),
+        Some(file) => fs::write(&file, text).map_err(|e| {
+            format!(
+                "Error when writing {} file '{}': {}",
+                title.to_lowercase(),
+                file,
+                e
+            )
+        })?,
     }
     Ok(())
 }
@@ -115,19 +406,34 @@ fn encode_ansi(str: String) -> Vec<u8> {
 struct ProductCsvExport {
     id: String,
     active: String,
+    slug: String,
     name: String,
     stock: Option<u32>,
-    price: f64,
-    price_cost: Option<f64>,
+    price: Money,
+    promotional_price: Option<Money>,
+    promotion_start: Option<String>,
+    promotion_end: Option<String>,
+    installments: Option<u32>,
+    installment_value: Option<Money>,
+    interest_free: Option<String>,
+    price_cost: Option<Money>,
     vendor_name: String,
     description: String,
+    category_id: Option<u32>,
     category: String,
     subcategory: String,
-    picture1: String,
-    picture2: String,
-    picture3: String,
-    picture4: String,
-    picture5: String,
+    weight: Option<u32>,
+    height: Option<u32>,
+    width: Option<u32>,
+    length: Option<u32>,
+    seo_title: String,
+    seo_description: String,
+    seo_keywords: String,
+    canonical_url: String,
+    old_url: String,
+    sku: String,
+    gtin: String,
+    brand: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -140,6 +446,52 @@ struct VariationCsvExport {
     name2: Option<String>,
     type3: Option<String>,
     name3: Option<String>,
-    pub price: f64,
+    pub price: Money,
+    pub promotional_price: Option<Money>,
+    pub sku: String,
+    pub gtin: String,
     pub picture: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn fills_picture_columns_without_dropping_pictures_silently() {
+        let products = vec![
+            Product::test("1").with_pictures(&["a"]),
+            Product::test("2").with_pictures(&["a", "b", "c"]),
+        ];
+        let layout = PictureLayout::for_products(Some(2), None, &products);
+        assert_eq!(vec!["a", ""], picture_columns(&products[0], &layout));
+        assert_eq!(vec!["a", "b"], picture_columns(&products[1], &layout));
+        let layout = PictureLayout::for_products(Some(0), None, &products);
+        assert_eq!(PictureLayout::AllColumns(3), layout);
+        assert_eq!(vec!["a", "", ""], picture_columns(&products[0], &layout));
+        let layout = PictureLayout::for_products(None, Some("|".to_owned()), &products);
+        assert_eq!(vec!["a|b|c"], picture_columns(&products[1], &layout));
+    }
+
+    #[test]
+    fn points_canonical_urls_to_the_new_store() {
+        let product = Product {
+            slug: "camiseta".to_owned(),
+            ..Default::default()
+        };
+        let category_tree = CategoryTree::from_products(&[]);
+        let id_map = IdMap::default();
+        let context = |new_store_url| ExportContext {
+            category_tree: &category_tree,
+            id_map: &id_map,
+            picture_layout: &PictureLayout::Columns(0),
+            new_store_url,
+        };
+        assert_eq!("", context(None).canonical_url(&product));
+        assert_eq!(
+            "https://nova.com.br/camiseta",
+            context(Some("https://nova.com.br/")).canonical_url(&product)
+        );
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{
+    categories,
+    money::{self, Locale, Money},
+    run::Product,
+};
+use rand::{rngs::StdRng, seq::index, SeedableRng};
+use std::collections::HashSet;
+
+// Which products of the report are exported. Ids and the fields of the report
+// are checked while it is read, so products left out are never scraped, and
+// the rest of the filter is checked after the products are enriched.
+#[derive(Debug, Default)]
+pub struct Selection {
+    pub only_ids: Option<HashSet<String>>,
+    pub filter: Option<Filter>,
+    pub offset: usize,
+    pub limit: usize,
+    pub sample: Option<usize>,
+    pub seed: Option<u64>,
+}
+
+impl Selection {
+    pub fn accepts_imported(&self, product: &Product) -> bool {
+        self.only_ids
+            .as_ref()
+            .is_none_or(|ids| ids.contains(&product.id))
+            && self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.eval(product, false) != Some(false))
+    }
+
+    pub fn accepts_enriched(&self, product: &Product) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.eval(product, true) == Some(true))
+    }
+
+    // Keeps the order of the report.
+    pub fn sample(&self, products: Vec<Product>) -> Vec<Product> {
+        let size = match self.sample {
+            Some(size) if size < products.len() => size,
+            _ => return products,
+        };
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let mut positions = index::sample(&mut rng, products.len(), size).into_vec();
+        positions.sort_unstable();
+        let mut positions = positions.into_iter().peekable();
+        products
+            .into_iter()
+            .enumerate()
+            .filter(|(position, _)| positions.next_if_eq(position).is_some())
+            .map(|(_, product)| product)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Id,
+    Name,
+    Vendor,
+    Price,
+    Cost,
+    Stock,
+    Category,
+    Description,
+    Pictures,
+    Variations,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Contains,
+}
+
+// Numbers are kept as Money, counts like stock included, so every number is
+// compared with the same precision.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(Money),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Compare(Field, Operator, Value),
+}
+
+impl Filter {
+    // None when the result depends on fields that are only known after the
+    // product is enriched.
+    fn eval(&self, product: &Product, enriched: bool) -> Option<bool> {
+        match self {
+            Filter::And(left, right) => {
+                match (left.eval(product, enriched), right.eval(product, enriched)) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                }
+            }
+            Filter::Or(left, right) => {
+                match (left.eval(product, enriched), right.eval(product, enriched)) {
+                    (Some(true), _) | (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                }
+            }
+            Filter::Not(filter) => filter.eval(product, enriched).map(|result| !result),
+            Filter::Compare(field, operator, value) => {
+                if !enriched && field.is_enriched() {
+                    return None;
+                }
+                Some(compare(field.values(product), *operator, value))
+            }
+        }
+    }
+}
+
+impl Field {
+    fn is_enriched(self) -> bool {
+        matches!(
+            self,
+            Field::Category | Field::Description | Field::Pictures | Field::Variations
+        )
+    }
+
+    // Categories have one value for the full path and one for each level, and
+    // the comparison is true when any of them matches.
+    fn values(self, product: &Product) -> Vec<Value> {
+        let text = |text: &str| vec![Value::Text(text.to_owned())];
+        let count = |count: usize| vec![Value::Number(Money::from_cents(count as i64 * 100))];
+        match self {
+            Field::Id => text(&product.id),
+            Field::Name => text(&product.name),
+            Field::Vendor => text(&product.vendor_name),
+            Field::Price => vec![Value::Number(product.price)],
+            Field::Cost => product.price_cost.map(Value::Number).into_iter().collect(),
+            Field::Stock => product
+                .stock
+                .map(|stock| count(stock as usize))
+                .unwrap_or_default(),
+            Field::Category => {
+                let mut values = text(&categories::category_path(product));
+                values.extend(product.categories.iter().map(|c| Value::Text(c.clone())));
+                values
+            }
+            Field::Description => text(&product.description),
+            Field::Pictures => count(product.pictures.len()),
+            Field::Variations => count(product.variations.len()),
+        }
+    }
+}
+
+// Missing values, like a product without cost, never match. Fields with many
+// values are different from a text only when none of them is equal to it.
+fn compare(values: Vec<Value>, operator: Operator, expected: &Value) -> bool {
+    match operator {
+        Operator::NotEqual => {
+            !values.is_empty()
+                && !values
+                    .iter()
+                    .any(|value| compare_value(value, Operator::Equal, expected))
+        }
+        _ => values
+            .iter()
+            .any(|value| compare_value(value, operator, expected)),
+    }
+}
+
+fn compare_value(value: &Value, operator: Operator, expected: &Value) -> bool {
+    match (value, expected) {
+        (Value::Text(value), Value::Text(expected)) => {
+            let (value, expected) = (value.trim().to_lowercase(), expected.to_lowercase());
+            match operator {
+                Operator::Equal => value == expected,
+                Operator::NotEqual => value != expected,
+                Operator::Contains => value.contains(&expected),
+                Operator::Less => value < expected,
+                Operator::LessOrEqual => value <= expected,
+                Operator::Greater => value > expected,
+                Operator::GreaterOrEqual => value >= expected,
+            }
+        }
+        (Value::Number(value), Value::Number(expected)) => match operator {
+            Operator::Equal => value == expected,
+            Operator::NotEqual => value != expected,
+            Operator::Contains => false,
+            Operator::Less => value < expected,
+            Operator::LessOrEqual => value <= expected,
+            Operator::Greater => value > expected,
+            Operator::GreaterOrEqual => value >= expected,
+        },
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Text(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 11] = ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "(", ")"];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        } else if c == '"' {
+            let end = rest[1..]
+                .find('"')
+                .ok_or_else(|| "text without closing quote".to_owned())?;
+            tokens.push(Token::Text(rest[1..=end].to_owned()));
+            end + 2
+        } else if c.is_ascii_digit() {
+            let length = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(rest[..length].to_owned()));
+            length
+        } else if c.is_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Identifier(rest[..length].to_lowercase()));
+            length
+        } else {
+            return Err(format!("unexpected '{}'", c));
+        };
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// Grammar, from the lowest precedence:
+// or = and ("||" and)*
+// and = unary ("&&" unary)*
+// unary = "!" unary | "(" or ")" | field operator value
+pub fn parse(text: &str) -> Result<Filter, String> {
+    let invalid = |e: String| format!("Invalid filter '{}': {}.", text, e);
+    let tokens = tokenize(text).map_err(invalid)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    let filter = parser.or().map_err(invalid)?;
+    match parser.next() {
+        None => Ok(filter),
+        Some(token) => Err(invalid(format!("unexpected {:?}", token))),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is(&mut self, symbol: &str) -> bool {
+        if matches!(self.tokens.get(self.position), Some(Token::Symbol(s)) if *s == symbol) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.next_is("||") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.unary()?;
+        while self.next_is("&&") {
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, String> {
+        if self.next_is("!") {
+            return Ok(Filter::Not(Box::new(self.unary()?)));
+        }
+        if self.next_is("(") {
+            let filter = self.or()?;
+            if !self.next_is(")") {
+                return Err("missing ')'".to_owned());
+            }
+            return Ok(filter);
+        }
+        let field = match self.next() {
+            Some(Token::Identifier(name)) => match name.as_str() {
+                "id" => Field::Id,
+                "name" => Field::Name,
+                "vendor" => Field::Vendor,
+                "price" => Field::Price,
+                "cost" => Field::Cost,
+                "stock" => Field::Stock,
+                "category" => Field::Category,
+                "description" => Field::Description,
+                "pictures" => Field::Pictures,
+                "variations" => Field::Variations,
+                _ => return Err(format!("unknown field '{}'", name)),
+            },
+            token => return Err(format!("expected a field, found {:?}", token)),
+        };
+        let operator = match self.next() {
+            Some(Token::Symbol("==")) => Operator::Equal,
+            Some(Token::Symbol("!=")) => Operator::NotEqual,
+            Some(Token::Symbol("<")) => Operator::Less,
+            Some(Token::Symbol("<=")) => Operator::LessOrEqual,
+            Some(Token::Symbol(">")) => Operator::Greater,
+            Some(Token::Symbol(">=")) => Operator::GreaterOrEqual,
+            Some(Token::Identifier(name)) if name == "contains" => Operator::Contains,
+            token => return Err(format!("expected an operator, found {:?}", token)),
+        };
+        let value = match self.next() {
+            Some(Token::Text(text)) => Value::Text(text),
+            Some(Token::Number(number)) => Value::Number(money::parse(&number, Locale::EnUs)?),
+            token => return Err(format!("expected a value, found {:?}", token)),
+        };
+        Ok(Filter::Compare(field, operator, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn product(vendor: &str, price: i64, categories: &[&str]) -> Product {
+        Product::test("1")
+            .with_vendor(vendor)
+            .with_price(price)
+            .with_categories(categories)
+    }
+
+    #[test]
+    fn evaluates_imported_fields_before_enrichment() -> Result<(), String> {
+        let selection = Selection {
+            filter: Some(parse(
+                r#"vendor == "Acme" && (price > 50 || !(category contains "promo"))"#,
+            )?),
+            ..Default::default()
+        };
+        let cheap = product("acme ", 4000, &["Roupas", "Promoções"]);
+        assert!(selection.accepts_imported(&cheap));
+        assert!(!selection.accepts_enriched(&cheap));
+        let expensive = product("Acme", 5050, &["Roupas"]);
+        assert!(selection.accepts_imported(&expensive));
+        assert!(selection.accepts_enriched(&expensive));
+        assert!(!selection.accepts_imported(&product("Other", 9000, &[])));
+        Ok(())
+    }
+
+    #[test]
+    fn compares_every_level_of_the_category_when_different() -> Result<(), String> {
+        let selection = Selection {
+            filter: Some(parse(r#"category != "Roupas""#)?),
+            ..Default::default()
+        };
+        assert!(!selection.accepts_enriched(&product("Acme", 100, &["Roupas", "Camisetas"])));
+        assert!(selection.accepts_enriched(&product("Acme", 100, &["Casa"])));
+        let without_cost = Selection {
+            filter: Some(parse("cost != 10")?),
+            ..Default::default()
+        };
+        assert!(!without_cost.accepts_imported(&product("Acme", 100, &[])));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_invalid_filters() {
+        assert_eq!(
+            "Invalid filter 'size > 1': unknown field 'size'.",
+            parse("size > 1").unwrap_err()
+        );
+        assert!(parse("price >").is_err());
+        assert!(parse("(price > 1").is_err());
+        assert!(parse("name == \"x").is_err());
+    }
+
+    #[test]
+    fn samples_keeping_order() {
+        let products = (1..=10)
+            .map(|id| Product {
+                id: id.to_string(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let selection = Selection {
+            sample: Some(3),
+            seed: Some(42),
+            ..Default::default()
+        };
+        let ids = selection
+            .sample(products.clone())
+            .into_iter()
+            .map(|p| p.id.parse::<u32>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(3, ids.len());
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(
+            ids,
+            selection
+                .sample(products)
+                .into_iter()
+                .map(|p| p.id.parse::<u32>().unwrap())
+                .collect::<Vec<_>>()
+        );
+    }
+}
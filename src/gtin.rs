@@ -0,0 +1,34 @@
+// EAN-8, UPC-A, EAN-13 and GTIN-14 codes end with a check digit, calculated
+// from the other digits weighted alternately by 3 and 1 from the right.
+pub fn is_valid(code: &str) -> bool {
+    if ![8, 12, 13, 14].contains(&code.len()) || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let digits = code
+        .bytes()
+        .map(|b| u32::from(b - b'0'))
+        .collect::<Vec<_>>();
+    let (check_digit, digits) = digits.split_last().unwrap();
+    let sum = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(position, digit)| if position % 2 == 0 { digit * 3 } else { *digit })
+        .sum::<u32>();
+    (10 - sum % 10) % 10 == *check_digit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_the_check_digit() {
+        assert!(is_valid("7891000315507"));
+        assert!(is_valid("96385074"));
+        assert!(is_valid("036000291452"));
+        assert!(!is_valid("7891000315508"));
+        assert!(!is_valid("789100031550"));
+        assert!(!is_valid("78910003155O7"));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{
+    importer,
+    run::{Product, Variation},
+    slug,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdMapping {
+    iluria_id: String,
+    export_id: String,
+    // Empty in id maps written before slugs were kept.
+    #[serde(default)]
+    slug: String,
+}
+
+// Ids exported for each Iluria product. Products keep the id they got in
+// previous runs, and new products get their Iluria id, unless it is already
+// taken, in which case they get the next number after the highest id in use.
+// The slug of each product is kept the same way, so its new page never moves.
+#[derive(Debug, Default)]
+pub struct IdMap {
+    mappings: Vec<IdMapping>,
+    positions: HashMap<String, usize>,
+    export_ids: HashSet<String>,
+}
+
+impl IdMap {
+    pub fn load(file: &str) -> Result<IdMap, String> {
+        let file_handle = File::open(file)
+            .map_err(|e| format!("Error when opening id map file '{}': {}", file, e))?;
+        let mut rdr = csv::Reader::from_reader(importer::open_file_and_decode(file_handle));
+        let mut id_map = IdMap::default();
+        let mut slugs = HashSet::new();
+        for result in rdr.deserialize() {
+            let mapping: IdMapping =
+                result.map_err(|e| format!("Could not read id map file '{}': {}", file, e))?;
+            if id_map.export_ids.contains(&mapping.export_id)
+                || id_map.positions.contains_key(&mapping.iluria_id)
+                || !mapping.slug.is_empty() && !slugs.insert(mapping.slug.clone())
+            {
+                return Err(format!(
+                    "Id map file '{}' has product {}, id {} or slug '{}' more than once.",
+                    file, mapping.iluria_id, mapping.export_id, mapping.slug
+                ));
+            }
+            id_map.insert(mapping);
+        }
+        printlnv!("Loaded {} ids from '{}'.", id_map.mappings.len(), file);
+        Ok(id_map)
+    }
+
+    pub fn assign_ids(&mut self, products: &[Product]) {
+        for product in products {
+            if self.positions.contains_key(&product.id) {
+                continue;
+            }
+            let export_id = if self.export_ids.contains(&product.id) {
+                let next_id = self
+                    .export_ids
+                    .iter()
+                    .filter_map(|id| id.parse::<u64>().ok())
+                    .max()
+                    .unwrap_or(0)
+                    + 1;
+                printlnv!(
+                    "Id {} is taken, product {} will be exported with id {}.",
+                    product.id,
+                    product.id,
+                    next_id
+                );
+                next_id.to_string()
+            } else {
+                product.id.clone()
+            };
+            self.insert(IdMapping {
+                iluria_id: product.id.clone(),
+                export_id,
+                slug: product.slug.clone(),
+            });
+        }
+    }
+
+    pub fn export_id(&self, iluria_id: &str) -> Option<&str> {
+        self.positions
+            .get(iluria_id)
+            .map(|&position| self.mappings[position].export_id.as_str())
+    }
+
+    pub fn slug(&self, iluria_id: &str) -> Option<&str> {
+        self.positions
+            .get(iluria_id)
+            .map(|&position| self.mappings[position].slug.as_str())
+            .filter(|slug| !slug.is_empty())
+    }
+
+    pub fn slugs(&self) -> impl Iterator<Item = &str> {
+        self.mappings
+            .iter()
+            .map(|mapping| mapping.slug.as_str())
+            .filter(|slug| !slug.is_empty())
+    }
+
+    // Only products that already have an id get a slug.
+    pub fn set_slug(&mut self, iluria_id: &str, slug: &str) {
+        if let Some(&position) = self.positions.get(iluria_id) {
+            self.mappings[position].slug = slug.to_owned();
+        }
+    }
+
+    pub fn into_mappings(self) -> Vec<IdMapping> {
+        self.mappings
+    }
+
+    fn insert(&mut self, mapping: IdMapping) {
+        self.positions
+            .insert(mapping.iluria_id.clone(), self.mappings.len());
+        self.export_ids.insert(mapping.export_id.clone());
+        self.mappings.push(mapping);
+    }
+}
+
+// Variations are identified by their product and their options, as Iluria
+// does not export any id for them. The options of the report, like
+// "Azul / P", give the same id as the names of the page, so the stock updates
+// find the variations exported before.
+pub fn variation_id(product_id: &str, names: &[&str]) -> String {
+    match variation_options(names) {
+        options if options.is_empty() => "".to_owned(),
+        options => format!("{}-{}", product_id, options.join("-")),
+    }
+}
+
+pub fn page_variation_id(product_id: &str, variation: &Variation) -> String {
+    variation_id(
+        product_id,
+        &[
+            &variation.name1,
+            variation.name2.as_deref().unwrap_or_default(),
+            variation.name3.as_deref().unwrap_or_default(),
+        ],
+    )
+}
+
+// The options of a variation, like "Azul / P" in the report or the names P
+// and Azul of the page, in the same order wherever they come from.
+pub fn variation_options(names: &[&str]) -> Vec<String> {
+    let mut options = names
+        .iter()
+        .flat_map(|name| name.split('/'))
+        .map(slug::slugify)
+        .filter(|option| !option.is_empty())
+        .collect::<Vec<_>>();
+    options.sort_unstable();
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn keeps_previous_ids_and_avoids_taken_ones() {
+        let mut id_map = IdMap::default();
+        id_map.insert(IdMapping {
+            iluria_id: "100".to_owned(),
+            export_id: "1".to_owned(),
+            slug: "".to_owned(),
+        });
+        id_map.insert(IdMapping {
+            iluria_id: "200".to_owned(),
+            export_id: "7".to_owned(),
+            slug: "".to_owned(),
+        });
+        id_map.assign_ids(&[
+            Product::test("200"),
+            Product::test("300"),
+            Product::test("1"),
+        ]);
+        assert_eq!(Some("7"), id_map.export_id("200"));
+        assert_eq!(Some("300"), id_map.export_id("300"));
+        assert_eq!(Some("301"), id_map.export_id("1"));
+        assert_eq!(Some("1"), id_map.export_id("100"));
+        assert_eq!(4, id_map.into_mappings().len());
+    }
+}
@@ -1,83 +1,126 @@
-use crate::VERBOSE;
-use serde::{de, Deserialize, Deserializer};
-use std::io::Read;
-use std::{fs::File, num::ParseFloatError};
+use crate::money::{self, Locale, Money};
+use crate::shipping::{self, Shipping};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufReader, Read};
 
-pub fn get_products_with_variations(file: &str) -> Result<Vec<ProductWithVariation>, String> {
-    let file = File::open(&std::path::Path::new(file))
+pub fn get_products_with_variations(
+    file: &str,
+    locale: Locale,
+) -> Result<impl Iterator<Item = Result<ProductWithVariation, String>>, String> {
+    let file = File::open(std::path::Path::new(file))
         .map_err(|err| format!("Error when opening summary file: {}", err))?;
-    let file_contents =
-        open_file_and_decode(file).map_err(|e| format!("Could not read text file: {}", e))?;
-    let mut rdr = csv::ReaderBuilder::new()
+    let rdr = csv::ReaderBuilder::new()
         .delimiter(b';')
-        .from_reader(file_contents.as_bytes());
-    let mut products = vec![];
-    for result in rdr.deserialize() {
-        let product: ProductWithVariation =
-            result.map_err(|e| format!("Could not map row: {}", e))?;
-        products.push(product);
-    }
-    unsafe {
-        if VERBOSE {
-            printlnpb!("Products read from csv file:");
-            for product_with_variation in products.iter() {
-                printlnpb!("{:?}", product_with_variation);
-            }
-            printlnpb!("");
-        }
-    }
-    Ok(products)
+        .from_reader(open_file_and_decode(file));
+    Ok(rdr.into_deserialize().map(move |result| {
+        let row: ProductRow = result.map_err(|e| match e.kind() {
+            csv::ErrorKind::Io(_) => format!("Could not read text file: {}", e),
+            _ => format!("Could not map row: {}", e),
+        })?;
+        let product = row.into_product_with_variation(locale)?;
+        printlnv!("Product read from csv file: {:?}", product);
+        Ok(product)
+    }))
 }
 
-fn open_file_and_decode(file: File) -> std::io::Result<String> {
-    let mut decoder = encoding_rs_io::DecodeReaderBytesBuilder::new()
+pub fn open_file_and_decode(file: File) -> impl Read {
+    encoding_rs_io::DecodeReaderBytesBuilder::new()
         .encoding(Some(encoding_rs::WINDOWS_1252))
-        .build(&file);
-    let mut contents = String::new();
-    decoder.read_to_string(&mut contents)?;
-    Ok(contents)
+        .build(BufReader::new(file))
 }
 
-fn number_with_comma<'de, D>(deserializer: D) -> Result<f64, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let str: String = Deserialize::deserialize(deserializer)?;
-    Ok(convert_number(&str).map_err(de::Error::custom)?)
+#[derive(Debug, Deserialize)]
+struct ProductRow {
+    #[serde(rename = "Produto")]
+    produto: String,
+    #[serde(rename = "Nome")]
+    nome: String,
+    #[serde(rename = "Variação", default)]
+    variacao: String,
+    #[serde(rename = "Estoque")]
+    #[serde(deserialize_with = "csv::invalid_option")]
+    estoque: Option<u32>,
+    #[serde(rename = "Preço")]
+    preco: String,
+    #[serde(rename = "Preço de custo")]
+    preco_de_custo: String,
+    #[serde(rename = "Nome do fornecedor")]
+    nome_do_fornecedor: String,
+    // Optional, in grams and centimeters.
+    #[serde(rename = "Peso", default)]
+    peso: String,
+    #[serde(rename = "Altura", default)]
+    altura: String,
+    #[serde(rename = "Largura", default)]
+    largura: String,
+    #[serde(rename = "Comprimento", default)]
+    comprimento: String,
+    #[serde(rename = "SKU", default)]
+    sku: String,
+    #[serde(rename = "EAN", alias = "GTIN", default)]
+    gtin: String,
+    #[serde(rename = "Marca", default)]
+    marca: String,
+    // Optional, the address of the product at stores that have one, like
+    // Nuvemshop.
+    #[serde(rename = "Handle", default)]
+    handle: String,
 }
 
-fn optional_number_with_comma<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let str: String = Deserialize::deserialize(deserializer)?;
-    if str.is_empty() {
-        return Ok(None);
+impl ProductRow {
+    fn into_product_with_variation(self, locale: Locale) -> Result<ProductWithVariation, String> {
+        let preco = money::parse(&self.preco, locale)
+            .map_err(|e| format!("Could not map price of product {}: {}", self.produto, e))?;
+        let preco_de_custo = if self.preco_de_custo.trim().is_empty() {
+            None
+        } else {
+            Some(money::parse(&self.preco_de_custo, locale).map_err(|e| {
+                format!(
+                    "Could not map cost price of product {}: {}",
+                    self.produto, e
+                )
+            })?)
+        };
+        let measure = |name: &str, text: &str| {
+            shipping::parse_measure(text, locale)
+                .map_err(|e| format!("Could not map {} of product {}: {}", name, self.produto, e))
+        };
+        let frete = Shipping {
+            weight: measure("weight", &self.peso)?,
+            height: measure("height", &self.altura)?,
+            width: measure("width", &self.largura)?,
+            length: measure("length", &self.comprimento)?,
+        };
+        Ok(ProductWithVariation {
+            produto: self.produto,
+            nome: self.nome,
+            variacao: self.variacao,
+            estoque: self.estoque,
+            preco,
+            preco_de_custo,
+            nome_do_fornecedor: self.nome_do_fornecedor,
+            frete,
+            sku: self.sku.trim().to_owned(),
+            gtin: self.gtin.trim().to_owned(),
+            marca: self.marca.trim().to_owned(),
+            handle: self.handle.trim().to_owned(),
+        })
     }
-    Ok(Some(convert_number(&str).map_err(de::Error::custom)?))
 }
 
-fn convert_number(str: &str) -> Result<f64, ParseFloatError> {
-    let x = str.replace(".", "").replace(",", ".");
-    let number = x.parse::<f64>()?;
-    Ok(number)
-}
-
-#[derive(Debug, Deserialize)]
+#[derive(Debug)]
 pub struct ProductWithVariation {
-    #[serde(rename = "Produto")]
     pub produto: String,
-    #[serde(rename = "Nome")]
     pub nome: String,
-    #[serde(rename = "Estoque")]
-    #[serde(deserialize_with = "csv::invalid_option")]
+    pub variacao: String,
     pub estoque: Option<u32>,
-    #[serde(rename = "Preço")]
-    #[serde(deserialize_with = "number_with_comma")]
-    pub preco: f64,
-    #[serde(rename = "Preço de custo")]
-    #[serde(deserialize_with = "optional_number_with_comma")]
-    pub preco_de_custo: Option<f64>,
-    #[serde(rename = "Nome do fornecedor")]
+    pub preco: Money,
+    pub preco_de_custo: Option<Money>,
     pub nome_do_fornecedor: String,
+    pub frete: Shipping,
+    pub sku: String,
+    pub gtin: String,
+    pub marca: String,
+    pub handle: String,
 }
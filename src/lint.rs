@@ -0,0 +1,213 @@
+use crate::{
+    gtin,
+    money::Money,
+    run::{Availability, Product},
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+// Severity of each check, from a TOML file like:
+// zero-price = "error"
+// no-pictures = "off"
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LintRules {
+    no_description: Severity,
+    no_pictures: Severity,
+    zero_price: Severity,
+    no_category: Severity,
+    variation_without_name: Severity,
+    no_shipping_data: Severity,
+    invalid_gtin: Severity,
+}
+
+impl Default for LintRules {
+    fn default() -> Self {
+        LintRules {
+            no_description: Severity::Warning,
+            no_pictures: Severity::Warning,
+            zero_price: Severity::Error,
+            no_category: Severity::Warning,
+            variation_without_name: Severity::Error,
+            no_shipping_data: Severity::Warning,
+            invalid_gtin: Severity::Error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub product_id: String,
+    pub product_name: String,
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub url: String,
+}
+
+pub fn load_rules(file: &str) -> Result<LintRules, String> {
+    let contents = fs::read_to_string(file)
+        .map_err(|e| format!("Error when opening lint rules file '{}': {}", file, e))?;
+    let rules = toml::from_str(&contents)
+        .map_err(|e| format!("Could not read lint rules file '{}': {}", file, e))?;
+    printlnv!("Lint rules: {:?}", rules);
+    Ok(rules)
+}
+
+pub fn lint_products(rules: &LintRules, products: &[Product]) -> Vec<Problem> {
+    let mut problems = vec![];
+    for product in products {
+        // Products not found have only the data of the report, and are
+        // exported as inactive.
+        if product.availability == Availability::NotFound {
+            continue;
+        }
+        let mut check = |rule: &'static str, severity: Severity, failed: bool, message: String| {
+            if failed && severity != Severity::Off {
+                problems.push(Problem {
+                    product_id: product.id.clone(),
+                    product_name: product.name.clone(),
+                    rule,
+                    severity,
+                    message,
+                    url: product.url.clone(),
+                });
+            }
+        };
+        check(
+            "no-description",
+            rules.no_description,
+            product.description.trim().is_empty(),
+            "Product has no description.".to_owned(),
+        );
+        check(
+            "no-pictures",
+            rules.no_pictures,
+            product.pictures.is_empty(),
+            "Product has no pictures.".to_owned(),
+        );
+        check(
+            "zero-price",
+            rules.zero_price,
+            product.price == Money::default(),
+            "Product price is zero.".to_owned(),
+        );
+        check(
+            "no-category",
+            rules.no_category,
+            product.categories.iter().all(|c| c.trim().is_empty()),
+            "Product has no category.".to_owned(),
+        );
+        // Tray does not calculate shipping without weight and dimensions.
+        let missing = product.shipping.missing();
+        check(
+            "no-shipping-data",
+            rules.no_shipping_data,
+            !missing.is_empty(),
+            format!("Product shipping data is missing: {}.", missing.join(", ")),
+        );
+        check(
+            "invalid-gtin",
+            rules.invalid_gtin,
+            !product.gtin.is_empty() && !gtin::is_valid(&product.gtin),
+            format!("Product GTIN '{}' is invalid.", product.gtin),
+        );
+        for (position, variation) in product.variations.iter().enumerate() {
+            check(
+                "zero-price",
+                rules.zero_price,
+                variation.price == Money::default(),
+                format!("Variation {} price is zero.", position + 1),
+            );
+            check(
+                "variation-without-name",
+                rules.variation_without_name,
+                variation.name1.trim().is_empty(),
+                format!("Variation {} has no name.", position + 1),
+            );
+            check(
+                "invalid-gtin",
+                rules.invalid_gtin,
+                !variation.gtin.is_empty() && !gtin::is_valid(&variation.gtin),
+                format!(
+                    "Variation {} GTIN '{}' is invalid.",
+                    position + 1,
+                    variation.gtin
+                ),
+            );
+        }
+    }
+    problems
+}
+
+pub fn count(problems: &[Problem], severity: Severity) -> usize {
+    problems.iter().filter(|p| p.severity == severity).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::Variation;
+    use crate::shipping::Shipping;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn reports_problems_with_configured_severities() {
+        let product = Product {
+            id: "1".to_owned(),
+            description: "Café".to_owned(),
+            categories: vec!["Bebidas".to_owned()],
+            shipping: Shipping {
+                weight: Some(250),
+                ..Default::default()
+            },
+            variations: vec![Variation {
+                type1: "Tamanho".to_owned(),
+                type2: None,
+                type3: None,
+                name1: "".to_owned(),
+                name2: None,
+                name3: None,
+                price: Money::from_cents(100),
+                gtin: "7891000315508".to_owned(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let rules: LintRules = toml::from_str("no-pictures = \"off\"").unwrap();
+        let problems = lint_products(&rules, &[product]);
+        assert_eq!(
+            vec![
+                ("zero-price", Severity::Error, "Product price is zero."),
+                (
+                    "no-shipping-data",
+                    Severity::Warning,
+                    "Product shipping data is missing: height, width, length."
+                ),
+                (
+                    "variation-without-name",
+                    Severity::Error,
+                    "Variation 1 has no name."
+                ),
+                (
+                    "invalid-gtin",
+                    Severity::Error,
+                    "Variation 1 GTIN '7891000315508' is invalid."
+                ),
+            ],
+            problems
+                .iter()
+                .map(|p| (p.rule, p.severity, p.message.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(3, count(&problems, Severity::Error));
+    }
+}
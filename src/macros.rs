@@ -2,7 +2,7 @@ macro_rules! printlnv {
         ($($arg:tt)*) => ({
             unsafe {
                 if $crate::VERBOSE {
-                    if let Some(bar) = &$crate::progressbar::BAR {
+                    if let Some(bar) = $crate::progressbar::bar() {
                         bar.println(format!($($arg)*));
                     } else {
                         println!($($arg)*);
@@ -14,13 +14,10 @@ macro_rules! printlnv {
 
 macro_rules! printlnpb {
         ($($arg:tt)*) => ({
-            #[allow(unused_unsafe)] // todo: this is a bug in the compiler, remove when https://github.com/rust-lang/rust/issues/49112 is fixed.
-            unsafe {
-                if let Some(bar) = &$crate::progressbar::BAR {
-                    bar.println(format!($($arg)*));
-                } else {
-                    println!($($arg)*);
-                }
+            if let Some(bar) = $crate::progressbar::bar() {
+                bar.println(format!($($arg)*));
+            } else {
+                println!($($arg)*);
             }
         })
     }
@@ -1,11 +1,29 @@
 #[macro_use]
 mod macros;
 mod args;
+mod categories;
+mod config;
+mod description;
 mod enricher;
 mod exporter;
+mod filter;
+mod gtin;
+mod ids;
 mod importer;
+mod lint;
+mod money;
+mod pictures;
+mod pricing;
 mod progressbar;
+mod redirects;
+mod report;
 mod run;
+mod shipping;
+mod simulator;
+mod slug;
+mod sources;
+mod stock;
+mod sync;
 use args::Args;
 
 static mut VERBOSE: bool = false;
@@ -26,6 +44,9 @@ fn run() -> Result<(), Option<String>> {
     unsafe {
         VERBOSE = args.verbose;
     }
+    if let Some(config_file) = &args.config_file {
+        printlnv!("Settings read from '{}'.", config_file);
+    }
     args.validate()?;
     printlnv!("Args are {:?}.", args);
     run::run(args)
@@ -0,0 +1,410 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    ops::{Add, Sub},
+    str::FromStr,
+};
+
+const CURRENCY_SYMBOLS: [&str; 7] = ["R$", "US$", "BRL", "USD", "$", "€", "EUR"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Locale {
+    #[default]
+    PtBr,
+    EnUs,
+}
+
+impl Locale {
+    fn separators(self) -> (char, char) {
+        match self {
+            Locale::PtBr => ('.', ','),
+            Locale::EnUs => (',', '.'),
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "pt-br" | "pt" => Ok(Locale::PtBr),
+            "en-us" | "en" => Ok(Locale::EnUs),
+            _ => Err(format!("Unknown locale '{}', use one of: pt-BR, en-US.", s)),
+        }
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Locale::PtBr => write!(f, "pt-BR"),
+            Locale::EnUs => write!(f, "en-US"),
+        }
+    }
+}
+
+// Money is kept as an integer amount of cents so that values read from the
+// report and the pages are exported exactly as they were typed. Values with
+// more than two decimal places are rounded half away from zero when parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money {
+    cents: i64,
+}
+
+impl Money {
+    pub fn from_cents(cents: i64) -> Money {
+        Money { cents }
+    }
+
+    pub fn cents(self) -> i64 {
+        self.cents
+    }
+}
+
+impl Money {
+    pub fn add_percentage(self, percentage: f64) -> Money {
+        let basis_points = (percentage * 100.0).round() as i128;
+        let cents = i128::from(self.cents) * (10_000 + basis_points);
+        let rounded = if cents < 0 {
+            (cents - 5_000) / 10_000
+        } else {
+            (cents + 5_000) / 10_000
+        };
+        Money::from_cents(rounded as i64)
+    }
+
+    // Moves the value to one whose cents are the same as `ending`'s, e.g. with
+    // an ending of 0,90 the value 12,34 becomes 12,90 rounding up, 11,90
+    // rounding down and 11,90 rounding to the nearest.
+    pub fn round_to_ending(self, ending: Money, rounding: Rounding) -> Money {
+        let ending = ending.cents.rem_euclid(100);
+        let down = self.cents.div_euclid(100) * 100 + ending;
+        let down = if down > self.cents { down - 100 } else { down };
+        let up = if down == self.cents { down } else { down + 100 };
+        Money::from_cents(match rounding {
+            Rounding::Up => up,
+            Rounding::Down => down,
+            Rounding::Nearest => {
+                if up - self.cents <= self.cents - down {
+                    up
+                } else {
+                    down
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    Up,
+    Down,
+    Nearest,
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(Rounding::Up),
+            "down" => Ok(Rounding::Down),
+            "nearest" => Ok(Rounding::Nearest),
+            _ => Err(format!(
+                "Unknown rounding '{}', use one of: up, down, nearest.",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}.{:02}",
+            if self.cents < 0 { "-" } else { "" },
+            self.cents.abs() / 100,
+            self.cents.abs() % 100
+        )
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text: String = Deserialize::deserialize(deserializer)?;
+        parse(&text, Locale::EnUs).map_err(de::Error::custom)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+    fn add(self, other: Money) -> Money {
+        Money::from_cents(self.cents + other.cents)
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+    fn sub(self, other: Money) -> Money {
+        Money::from_cents(self.cents - other.cents)
+    }
+}
+
+// Accepts values with or without a currency symbol and thousands separators,
+// like "R$ 1.234,56", "1234.56", "US$1,234.56" or "-12,5". When a single
+// separator could be either a thousands or a decimal separator ("1.234") the
+// locale decides.
+pub fn parse(text: &str, locale: Locale) -> Result<Money, String> {
+    let invalid = |reason: &str| format!("Invalid money value '{}': {}.", text, reason);
+    let mut value: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let negative = value.starts_with('-');
+    if negative {
+        value.remove(0);
+    }
+    if let Some(symbol) = CURRENCY_SYMBOLS.iter().find(|s| value.starts_with(*s)) {
+        value = value[symbol.len()..].to_owned();
+    } else if let Some(symbol) = CURRENCY_SYMBOLS.iter().find(|s| value.ends_with(*s)) {
+        value.truncate(value.len() - symbol.len());
+    }
+    let negative = if value.starts_with('-') {
+        value.remove(0);
+        !negative
+    } else {
+        negative
+    };
+    if value.is_empty() {
+        return Err(invalid("there is no number"));
+    }
+    if let Some(c) = value
+        .chars()
+        .find(|c| !c.is_ascii_digit() && *c != '.' && *c != ',')
+    {
+        return Err(invalid(&format!("unexpected character '{}'", c)));
+    }
+    let decimal_separator = match (value.rfind('.'), value.rfind(',')) {
+        (None, None) => None,
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) => single_separator_role(&value, '.', locale),
+        (None, Some(_)) => single_separator_role(&value, ',', locale),
+    };
+    let (integer_part, decimal_part) = match decimal_separator {
+        None => (value.as_str(), ""),
+        Some(separator) => {
+            let position = value.rfind(separator).unwrap();
+            (&value[..position], &value[position + 1..])
+        }
+    };
+    if decimal_part.contains(|c: char| !c.is_ascii_digit()) {
+        return Err(invalid("misplaced separator"));
+    }
+    let groups: Vec<&str> = integer_part.split(['.', ',']).collect();
+    if groups.len() > 1 {
+        let thousands_separator = integer_part.chars().find(|c| !c.is_ascii_digit()).unwrap();
+        if integer_part.contains(|c: char| !c.is_ascii_digit() && c != thousands_separator)
+            || Some(thousands_separator) == decimal_separator
+            || groups[0].is_empty()
+            || groups[0].len() > 3
+            || groups[1..].iter().any(|g| g.len() != 3)
+        {
+            return Err(invalid("misplaced thousands separator"));
+        }
+    }
+    let units = groups.concat();
+    if units.len() > 15 {
+        return Err(invalid("value is too large"));
+    }
+    let units = if units.is_empty() {
+        0
+    } else {
+        units.parse::<i64>().map_err(|e| invalid(&e.to_string()))?
+    };
+    let mut decimals = decimal_part.bytes().map(|b| i64::from(b - b'0'));
+    let cents = units * 100 + decimals.next().unwrap_or(0) * 10 + decimals.next().unwrap_or(0);
+    let cents = if decimals.next().unwrap_or(0) >= 5 {
+        cents + 1
+    } else {
+        cents
+    };
+    Ok(Money::from_cents(if negative { -cents } else { cents }))
+}
+
+fn single_separator_role(value: &str, separator: char, locale: Locale) -> Option<char> {
+    if value.matches(separator).count() > 1 {
+        return None;
+    }
+    let digits_after = value.len() - value.rfind(separator).unwrap() - 1;
+    if digits_after == 3 && locale.separators().0 == separator {
+        None
+    } else {
+        Some(separator)
+    }
+}
+
+pub fn format(value: Money, locale: Locale) -> String {
+    let (thousands_separator, decimal_separator) = locale.separators();
+    let units = (value.cents.abs() / 100).to_string();
+    let mut grouped = String::new();
+    for (i, c) in units.chars().enumerate() {
+        if i > 0 && (units.len() - i).is_multiple_of(3) {
+            grouped.push(thousands_separator);
+        }
+        grouped.push(c);
+    }
+    let sign = if value.cents < 0 { "-" } else { "" };
+    let symbol = match locale {
+        Locale::PtBr => "R$ ",
+        Locale::EnUs => "$",
+    };
+    format!(
+        "{}{}{}{}{:02}",
+        sign,
+        symbol,
+        grouped,
+        decimal_separator,
+        value.cents.abs() % 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_brazilian_values() -> Result<(), String> {
+        assert_eq!(
+            Money::from_cents(123456),
+            parse("R$ 1.234,56", Locale::PtBr)?
+        );
+        assert_eq!(
+            Money::from_cents(123456),
+            parse("R$\u{a0}1.234,56", Locale::PtBr)?
+        );
+        assert_eq!(
+            Money::from_cents(123456700),
+            parse("1.234.567", Locale::PtBr)?
+        );
+        assert_eq!(Money::from_cents(123400), parse("1.234", Locale::PtBr)?);
+        assert_eq!(Money::from_cents(5990), parse("59,9", Locale::PtBr)?);
+        assert_eq!(Money::from_cents(-1250), parse("-R$ 12,50", Locale::PtBr)?);
+        Ok(())
+    }
+
+    #[test]
+    fn parses_values_already_in_decimal_point_form() -> Result<(), String> {
+        assert_eq!(Money::from_cents(123456), parse("1234.56", Locale::PtBr)?);
+        assert_eq!(Money::from_cents(123450), parse("1234.5", Locale::PtBr)?);
+        assert_eq!(Money::from_cents(123456), parse("1,234.56", Locale::PtBr)?);
+        Ok(())
+    }
+
+    #[test]
+    fn parses_american_values() -> Result<(), String> {
+        assert_eq!(
+            Money::from_cents(123456),
+            parse("US$ 1,234.56", Locale::EnUs)?
+        );
+        assert_eq!(Money::from_cents(123456), parse("$1,234.56", Locale::EnUs)?);
+        assert_eq!(Money::from_cents(123), parse("1.234", Locale::EnUs)?);
+        assert_eq!(Money::from_cents(123400), parse("1,234", Locale::EnUs)?);
+        assert_eq!(Money::from_cents(1000), parse("10 USD", Locale::EnUs)?);
+        Ok(())
+    }
+
+    #[test]
+    fn rounds_extra_decimal_places_half_away_from_zero() -> Result<(), String> {
+        assert_eq!(Money::from_cents(124), parse("1.235", Locale::EnUs)?);
+        assert_eq!(Money::from_cents(123), parse("1,2349", Locale::PtBr)?);
+        assert_eq!(Money::from_cents(-1), parse("-0,005", Locale::PtBr)?);
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_invalid_values() {
+        assert!(parse("", Locale::PtBr).is_err());
+        assert!(parse("R$ ", Locale::PtBr).is_err());
+        assert!(parse("abc", Locale::PtBr).is_err());
+        assert!(parse("1.23.45", Locale::PtBr).is_err());
+        assert!(parse("12.34,5.6", Locale::PtBr).is_err());
+    }
+
+    #[test]
+    fn displays_values_as_plain_decimals() {
+        assert_eq!("59.90", Money::from_cents(5990).to_string());
+        assert_eq!("0.05", Money::from_cents(5).to_string());
+        assert_eq!("-12.50", Money::from_cents(-1250).to_string());
+    }
+
+    #[test]
+    fn adds_percentages_rounding_half_away_from_zero() {
+        assert_eq!(
+            Money::from_cents(1080),
+            Money::from_cents(1000).add_percentage(8.0)
+        );
+        assert_eq!(
+            Money::from_cents(6469),
+            Money::from_cents(5990).add_percentage(8.0)
+        );
+        assert_eq!(
+            Money::from_cents(900),
+            Money::from_cents(1000).add_percentage(-10.0)
+        );
+    }
+
+    #[test]
+    fn rounds_to_ending() {
+        let ending = Money::from_cents(90);
+        let value = Money::from_cents(1234);
+        assert_eq!(
+            Money::from_cents(1290),
+            value.round_to_ending(ending, Rounding::Up)
+        );
+        assert_eq!(
+            Money::from_cents(1190),
+            value.round_to_ending(ending, Rounding::Down)
+        );
+        assert_eq!(
+            Money::from_cents(1190),
+            value.round_to_ending(ending, Rounding::Nearest)
+        );
+        assert_eq!(
+            Money::from_cents(1290),
+            Money::from_cents(1240).round_to_ending(ending, Rounding::Nearest)
+        );
+        assert_eq!(
+            Money::from_cents(1190),
+            Money::from_cents(1190).round_to_ending(ending, Rounding::Up)
+        );
+        assert_eq!(
+            Money::from_cents(1300),
+            Money::from_cents(1234).round_to_ending(Money::default(), Rounding::Up)
+        );
+    }
+
+    #[test]
+    fn formats_values() {
+        assert_eq!(
+            "R$ 1.234,56",
+            format(Money::from_cents(123456), Locale::PtBr)
+        );
+        assert_eq!("R$ 0,90", format(Money::from_cents(90), Locale::PtBr));
+        assert_eq!(
+            "-R$ 1.234.567,00",
+            format(Money::from_cents(-123456700), Locale::PtBr)
+        );
+        assert_eq!("$1,234.56", format(Money::from_cents(123456), Locale::EnUs));
+        assert_eq!("$123.00", format(Money::from_cents(12300), Locale::EnUs));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{run::Product, slug};
+use scraper::{Html, Selector};
+use std::{collections::HashMap, fs, path::Path, time::Duration};
+
+// Pictures of the products and of their variations, and the images of the
+// descriptions, are downloaded once each to the pictures directory. They are
+// then moved to the pictures url, where the directory is going to be hosted, as
+// they would break when the old store closes. Pictures that fail to download
+// keep their address.
+pub fn download_pictures(
+    products: &[Product],
+    dir: &str,
+    pictures_url: &str,
+    simulate: bool,
+) -> Result<HashMap<String, String>, String> {
+    let mut urls = vec![];
+    for product in products {
+        urls.extend(product.pictures.iter().cloned());
+        urls.extend(product.variations.iter().filter_map(|v| v.picture.clone()));
+        urls.extend(description_images(product)?);
+    }
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+        .map_err(|e| format!("Could not create the client to download pictures: {}", e))?;
+    let mut new_urls = HashMap::new();
+    let mut failures = 0;
+    for url in urls {
+        if new_urls.contains_key(&url) {
+            continue;
+        }
+        let file = file_name(&url);
+        let path = Path::new(dir).join(&file);
+        if simulate {
+            printlnv!("Simulating download of {} to {}", url, path.display());
+        } else if path.exists() {
+            printlnv!("Picture {} was already downloaded.", url);
+        } else {
+            printlnv!("Downloading {} to {}", url, path.display());
+            if let Err(e) = download(&client, &url, &path) {
+                printlnpb!("Warning: could not download picture {}: {}", url, e);
+                failures += 1;
+                continue;
+            }
+        }
+        new_urls.insert(
+            url,
+            format!("{}/{}", pictures_url.trim_end_matches('/'), file),
+        );
+    }
+    printlnpb!(
+        "{} pictures are at {}, {} could not be downloaded.",
+        new_urls.len(),
+        dir,
+        failures
+    );
+    Ok(new_urls)
+}
+
+// Pictures of products and variations that were downloaded go to their new
+// address. The images of the descriptions are moved when they are cleaned.
+pub fn move_pictures(new_urls: &HashMap<String, String>, products: &mut [Product]) {
+    for product in products.iter_mut() {
+        for picture in product.pictures.iter_mut() {
+            if let Some(new_url) = new_urls.get(picture) {
+                *picture = new_url.clone();
+            }
+        }
+        for variation in product.variations.iter_mut() {
+            if let Some(new_url) = variation.picture.as_ref().and_then(|p| new_urls.get(p)) {
+                variation.picture = Some(new_url.clone());
+            }
+        }
+    }
+}
+
+// Images with a relative address are relative to the product page. Images
+// inside the page, as data, are left alone.
+pub fn description_images(product: &Product) -> Result<Vec<String>, String> {
+    let selector =
+        Selector::parse("img[src]").map_err(|e| format!("Could not get images: {:?}", e))?;
+    let product_url = url::Url::parse(&product.url).ok();
+    Ok(Html::parse_fragment(&product.description)
+        .select(&selector)
+        .filter_map(|img| img.value().attr("src"))
+        .map(|src| src.trim())
+        .filter(|src| !src.is_empty() && !src.starts_with("data:"))
+        .filter_map(|src| match &product_url {
+            Some(product_url) => product_url.join(src).ok().map(|url| url.to_string()),
+            None => url::Url::parse(src).ok().map(|url| url.to_string()),
+        })
+        .collect())
+}
+
+// The name of the file in the url, made safe, and a hash of the whole url, as
+// many pictures of Iluria have the same name in different directories. The
+// same url always gets the same file, in any run, so pictures downloaded
+// before are reused. Files are hosted at the new store, so only image
+// extensions are kept, and any other file is saved as jpg.
+fn file_name(url: &str) -> String {
+    let name = url::Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .and_then(|mut segments| segments.next_back().map(percent_decode))
+        })
+        .unwrap_or_default();
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension))
+            if IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()) =>
+        {
+            (stem.to_owned(), extension.to_lowercase())
+        }
+        _ => (name, "jpg".to_owned()),
+    };
+    let stem = match slug::slugify(&stem) {
+        stem if stem.is_empty() => "imagem".to_owned(),
+        stem => stem,
+    };
+    format!("{}-{:012x}.{}", stem, fnv_hash(url) >> 16, extension)
+}
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+
+// FNV-1a, which unlike the hasher of the standard library is the same in every
+// Rust version.
+fn fnv_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut position = 0;
+    while position < bytes.len() {
+        let hex = bytes
+            .get(position + 1..position + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[position], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                position += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                position += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn download(client: &reqwest::blocking::Client, url: &str, path: &Path) -> Result<(), String> {
+    let resp = client
+        .get(url)
+        .header("user-agent", "Mozilla/5.0")
+        .send()
+        .map_err(|e| format!("{}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("status code {}", resp.status()));
+    }
+    let bytes = resp.bytes().map_err(|e| format!("{}", e))?;
+    // A download that is interrupted must not look finished to the next run.
+    let partial = path.with_extension("part");
+    fs::write(&partial, bytes)
+        .and_then(|_| fs::rename(&partial, path))
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::Variation;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn finds_images_of_descriptions_and_names_their_files() -> Result<(), String> {
+        let product = Product {
+            url: "http://loja.com.br/pd-1".to_owned(),
+            description: "<p><img src=\"/imagens/Tabela de Medidas.PNG\">\
+                          <img src=\"https://cdn.iluria.com/1/foto\">\
+                          <img src=\"data:image/png;base64,AAAA\"></p>"
+                .to_owned(),
+            ..Default::default()
+        };
+        let images = description_images(&product)?;
+        assert_eq!(
+            vec![
+                "http://loja.com.br/imagens/Tabela%20de%20Medidas.PNG",
+                "https://cdn.iluria.com/1/foto"
+            ],
+            images
+        );
+        let files = [
+            file_name(&images[0]),
+            file_name(&images[1]),
+            file_name("https://cdn.iluria.com/2/foto"),
+        ];
+        assert!(files[0].starts_with("tabela-de-medidas-") && files[0].ends_with(".png"));
+        assert!(files[1].starts_with("foto-") && files[1].ends_with(".jpg"));
+        assert_ne!(files[1], files[2]);
+        assert_eq!(files[0], file_name(&images[0]));
+        assert!(file_name("https://loja.com.br/imagens/foto.svg").ends_with(".jpg"));
+        assert!(file_name("https://loja.com.br/foto.php?id=1").ends_with(".jpg"));
+        assert!(file_name("https://loja.com.br/imagens/foto.WEBP").ends_with(".webp"));
+        Ok(())
+    }
+
+    #[test]
+    fn moves_downloaded_pictures_of_products_and_variations() {
+        let mut products = vec![Product {
+            pictures: vec![
+                "http://img.com/1.jpg".to_owned(),
+                "http://img.com/2.jpg".to_owned(),
+            ],
+            variations: vec![Variation {
+                picture: Some("http://img.com/1.jpg".to_owned()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+        let new_urls = [(
+            "http://img.com/1.jpg".to_owned(),
+            "https://fotos.com.br/1.jpg".to_owned(),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        move_pictures(&new_urls, &mut products);
+        assert_eq!(
+            vec!["https://fotos.com.br/1.jpg", "http://img.com/2.jpg"],
+            products[0].pictures
+        );
+        assert_eq!(
+            Some("https://fotos.com.br/1.jpg"),
+            products[0].variations[0].picture.as_deref()
+        );
+    }
+}
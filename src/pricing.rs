@@ -0,0 +1,357 @@
+use crate::categories;
+use crate::money::{self, Locale, Money, Rounding};
+use crate::run::Product;
+use serde::Deserialize;
+use std::fs;
+
+#[derive(Debug)]
+pub struct PriceRule {
+    name: String,
+    vendor: Option<String>,
+    category: Option<String>,
+    min_price: Option<Money>,
+    max_price: Option<Money>,
+    percentage: Option<f64>,
+    fixed: Option<Money>,
+    min_margin: Option<f64>,
+    rounding: Option<(Rounding, Money)>,
+}
+
+pub fn load_rules(file: &str, locale: Locale) -> Result<Vec<PriceRule>, String> {
+    let contents = fs::read_to_string(file)
+        .map_err(|e| format!("Error when opening price rules file '{}': {}", file, e))?;
+    let rules_file: RulesFile = toml::from_str(&contents)
+        .map_err(|e| format!("Could not read price rules file '{}': {}", file, e))?;
+    let rules = rules_file
+        .rules
+        .into_iter()
+        .enumerate()
+        .map(|(i, rule)| rule.into_price_rule(i + 1, locale))
+        .collect::<Result<Vec<_>, String>>()?;
+    printlnv!("Price rules: {:?}", rules);
+    Ok(rules)
+}
+
+pub fn apply_rules(rules: &[PriceRule], products: &mut [Product], locale: Locale) {
+    let mut changes = 0;
+    for product in products.iter_mut() {
+        let cost = product.price_cost;
+        let new_price = apply_matching_rules(rules, product, product.price, cost);
+        if new_price != product.price {
+            printlnpb!(
+                "{} '{}': {} -> {}",
+                product.id,
+                product.name,
+                money::format(product.price, locale),
+                money::format(new_price, locale)
+            );
+            changes += 1;
+        }
+        // The promotional price follows the same rules as the regular one.
+        let promotional_price = product.promotion.as_ref().map(|promotion| {
+            (
+                promotion.price,
+                apply_matching_rules(rules, product, promotion.price, cost),
+            )
+        });
+        match promotional_price {
+            Some((_, new_promotional_price)) if new_promotional_price >= new_price => {
+                printlnpb!(
+                    "Warning: promotion of {} '{}' was removed, its price {} is not below the price {}.",
+                    product.id,
+                    product.name,
+                    money::format(new_promotional_price, locale),
+                    money::format(new_price, locale)
+                );
+                product.promotion = None;
+                changes += 1;
+            }
+            Some((price, new_promotional_price)) if new_promotional_price != price => {
+                printlnpb!(
+                    "{} '{}' (promotion): {} -> {}",
+                    product.id,
+                    product.name,
+                    money::format(price, locale),
+                    money::format(new_promotional_price, locale)
+                );
+                if let Some(promotion) = product.promotion.as_mut() {
+                    promotion.price = new_promotional_price;
+                }
+                changes += 1;
+            }
+            _ => {}
+        }
+        let mut variations = std::mem::take(&mut product.variations);
+        for variation in variations.iter_mut() {
+            let new_price = apply_matching_rules(rules, product, variation.price, cost);
+            if new_price != variation.price {
+                printlnpb!(
+                    "{} '{}' ({}): {} -> {}",
+                    product.id,
+                    product.name,
+                    variation.name1,
+                    money::format(variation.price, locale),
+                    money::format(new_price, locale)
+                );
+                variation.price = new_price;
+                changes += 1;
+            }
+            let promotional_price = match variation.promotional_price {
+                Some(price) => price,
+                None => continue,
+            };
+            let new_promotional_price =
+                apply_matching_rules(rules, product, promotional_price, cost);
+            if new_promotional_price >= new_price {
+                printlnpb!(
+                    "Warning: promotion of {} '{}' ({}) was removed, its price {} is not below the price {}.",
+                    product.id,
+                    product.name,
+                    variation.name1,
+                    money::format(new_promotional_price, locale),
+                    money::format(new_price, locale)
+                );
+                variation.promotional_price = None;
+                changes += 1;
+            } else if new_promotional_price != promotional_price {
+                printlnpb!(
+                    "{} '{}' ({}, promotion): {} -> {}",
+                    product.id,
+                    product.name,
+                    variation.name1,
+                    money::format(promotional_price, locale),
+                    money::format(new_promotional_price, locale)
+                );
+                variation.promotional_price = Some(new_promotional_price);
+                changes += 1;
+            }
+        }
+        product.variations = variations;
+        product.price = new_price;
+    }
+    printlnpb!("Price rules changed {} prices.", changes);
+}
+
+pub fn uses_categories(rules: &[PriceRule]) -> bool {
+    rules.iter().any(|rule| rule.category.is_some())
+}
+
+fn apply_matching_rules(
+    rules: &[PriceRule],
+    product: &Product,
+    price: Money,
+    cost: Option<Money>,
+) -> Money {
+    rules
+        .iter()
+        .filter(|rule| rule.matches(product, price))
+        .fold(price, |price, rule| {
+            let new_price = rule.apply(price, cost);
+            printlnv!(
+                "Rule '{}' changed price of product {} from {} to {}.",
+                rule.name,
+                product.id,
+                price,
+                new_price
+            );
+            new_price
+        })
+}
+
+impl PriceRule {
+    fn matches(&self, product: &Product, price: Money) -> bool {
+        let same = |expected: &Option<String>, value: &str| match expected {
+            None => true,
+            Some(expected) => expected.to_lowercase() == value.trim().to_lowercase(),
+        };
+        same(&self.vendor, &product.vendor_name)
+            && (same(&self.category, &categories::category_path(product))
+                || product
+                    .categories
+                    .iter()
+                    .any(|category| same(&self.category, category)))
+            && self.min_price.is_none_or(|min| price >= min)
+            && self.max_price.is_none_or(|max| price <= max)
+    }
+
+    fn apply(&self, price: Money, cost: Option<Money>) -> Money {
+        let mut price = price;
+        if let Some(percentage) = self.percentage {
+            price = price.add_percentage(percentage);
+        }
+        if let Some(fixed) = self.fixed {
+            price = price + fixed;
+        }
+        if let (Some(margin), Some(cost)) = (self.min_margin, cost) {
+            let minimum = cost.add_percentage(margin);
+            if price < minimum {
+                price = minimum;
+            }
+        }
+        if let Some((rounding, ending)) = self.rounding {
+            price = price.round_to_ending(ending, rounding);
+        }
+        price
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDefinition {
+    name: Option<String>,
+    vendor: Option<String>,
+    category: Option<String>,
+    min_price: Option<String>,
+    max_price: Option<String>,
+    percentage: Option<f64>,
+    fixed: Option<String>,
+    min_margin: Option<f64>,
+    round: Option<String>,
+    ending: Option<String>,
+}
+
+impl RuleDefinition {
+    fn into_price_rule(self, position: usize, locale: Locale) -> Result<PriceRule, String> {
+        let name = self.name.unwrap_or_else(|| format!("#{}", position));
+        let parse = |value: &Option<String>| -> Result<Option<Money>, String> {
+            value
+                .as_ref()
+                .map(|v| money::parse(v, locale))
+                .transpose()
+                .map_err(|e| format!("Invalid price rule '{}': {}", name, e))
+        };
+        let rounding = match (&self.round, parse(&self.ending)?) {
+            (None, None) => None,
+            (None, Some(_)) => {
+                return Err(format!(
+                    "Invalid price rule '{}': 'ending' needs 'round' to be set.",
+                    name
+                ))
+            }
+            (Some(round), ending) => Some((
+                round
+                    .parse::<Rounding>()
+                    .map_err(|e| format!("Invalid price rule '{}': {}", name, e))?,
+                ending.unwrap_or_default(),
+            )),
+        };
+        Ok(PriceRule {
+            vendor: self.vendor,
+            category: self.category,
+            min_price: parse(&self.min_price)?,
+            max_price: parse(&self.max_price)?,
+            percentage: self.percentage,
+            fixed: parse(&self.fixed)?,
+            min_margin: self.min_margin,
+            rounding,
+            name,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::{Promotion, Variation};
+    use pretty_assertions::assert_eq;
+
+    fn product(vendor: &str, price: i64, cost: Option<i64>) -> Product {
+        Product::test("1")
+            .with_vendor(vendor)
+            .with_price(price)
+            .with_cost(cost)
+            .with_categories(&["Roupas", "Camisetas"])
+            .with_variations(vec![Variation {
+                type1: "Tamanho".to_owned(),
+                name1: "G".to_owned(),
+                price: Money::from_cents(price + 500),
+                ..Default::default()
+            }])
+    }
+
+    fn rules(text: &str) -> Vec<PriceRule> {
+        let rules_file: RulesFile = toml::from_str(text).unwrap();
+        rules_file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| rule.into_price_rule(i + 1, Locale::PtBr).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn applies_matching_rules_in_order() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            percentage = 8
+            [[rule]]
+            vendor = "acme"
+            round = "up"
+            ending = "0,90"
+            [[rule]]
+            category = "Sapatos"
+            fixed = "100,00"
+            "#,
+        );
+        let mut products = vec![product("ACME", 1000, None), product("Other", 1000, None)];
+        apply_rules(&rules, &mut products, Locale::PtBr);
+        assert_eq!(Money::from_cents(1090), products[0].price);
+        assert_eq!(Money::from_cents(1690), products[0].variations[0].price);
+        assert_eq!(Money::from_cents(1080), products[1].price);
+        assert_eq!(Money::from_cents(1620), products[1].variations[0].price);
+    }
+
+    #[test]
+    fn keeps_minimum_margin_over_cost() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            max_price = "20,00"
+            min_margin = 50
+            "#,
+        );
+        let mut products = vec![product("", 1000, Some(800)), product("", 1000, None)];
+        apply_rules(&rules, &mut products, Locale::PtBr);
+        assert_eq!(Money::from_cents(1200), products[0].price);
+        assert_eq!(Money::from_cents(1500), products[0].variations[0].price);
+        assert_eq!(Money::from_cents(1000), products[1].price);
+    }
+
+    #[test]
+    fn removes_promotions_not_below_the_price() {
+        let rules = rules(
+            r#"
+            [[rule]]
+            round = "up"
+            ending = "0,90"
+            "#,
+        );
+        let promotion = |price: i64| Promotion {
+            price: Money::from_cents(price),
+            start: None,
+            end: None,
+        };
+        let mut products = vec![product("", 1000, None), product("", 1000, None)];
+        products[0].promotion = Some(promotion(1050));
+        products[0].variations[0].promotional_price = Some(Money::from_cents(1400));
+        products[1].promotion = Some(promotion(900));
+        apply_rules(&rules, &mut products, Locale::PtBr);
+        assert_eq!(Money::from_cents(1090), products[0].price);
+        assert_eq!(None, products[0].promotion);
+        assert_eq!(
+            Some(Money::from_cents(1490)),
+            products[0].variations[0].promotional_price
+        );
+        assert_eq!(
+            Some(Money::from_cents(990)),
+            products[1].promotion.as_ref().map(|p| p.price)
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use indicatif::{ProgressBar, ProgressStyle};
 
-pub static mut BAR: Option<ProgressBar> = None;
+static mut BAR: Option<ProgressBar> = None;
+
+pub fn bar() -> Option<&'static ProgressBar> {
+    unsafe { (*std::ptr::addr_of!(BAR)).as_ref() }
+}
 
 pub fn start_progress_bar(len: u64) {
     unsafe {
@@ -11,29 +15,25 @@ pub fn start_progress_bar(len: u64) {
 }
 
 pub fn set_progress_bar_len(len: u64) {
-    unsafe {
-        if let Some(bar) = &BAR {
-            let old_position = bar.position() as f64;
-            let old_len = bar.length() as f64;
-            bar.set_position(0); // todo: remove this and set them pos and len together when https://github.com/mitsuhiko/indicatif/issues/236 is done
-            bar.set_length(len);
-            bar.set_position((old_position / old_len * (len as f64)).round() as u64);
-        }
+    if let Some(bar) = bar() {
+        let old_position = bar.position() as f64;
+        let old_len = bar.length() as f64;
+        bar.set_position(0); // todo: remove this and set them pos and len together when https://github.com/mitsuhiko/indicatif/issues/236 is done
+        bar.set_length(len);
+        bar.set_position((old_position / old_len * (len as f64)).round() as u64);
     }
 }
 
 pub fn inc_progress_bar(amount: u64) {
-    unsafe {
-        if let Some(bar) = &BAR {
-            bar.inc(amount);
-        }
+    if let Some(bar) = bar() {
+        bar.inc(amount);
     }
 }
 
 pub fn finish_progress_bar() {
-    unsafe {
-        if let Some(bar) = &BAR {
-            bar.finish();
+    if let Some(bar) = bar() {
+        bar.finish();
+        unsafe {
             BAR = None;
         }
     }
@@ -0,0 +1,236 @@
+use crate::{categories::CategoryTree, ids::IdMap, run::Product, slug::UniqueSlugs};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Serialize)]
+pub struct Redirect {
+    kind: &'static str,
+    old_url: String,
+    new_url: String,
+}
+
+// Product slugs share the root of the new store with the top level
+// categories, so they can't use any of their slugs. Products keep the slug of
+// the id map, or else of the previous catalog, so their old page always
+// redirects to the same new one, and only products without one get a slug that
+// no other product of the map or the catalog has.
+pub fn assign_product_slugs(
+    products: &mut [Product],
+    category_tree: &CategoryTree,
+    id_map: &mut IdMap,
+    previous_catalog: &[Product],
+) {
+    let mut slugs = UniqueSlugs::default();
+    let category_slugs = category_tree.top_level_slugs().collect::<HashSet<_>>();
+    for category_slug in category_slugs.iter() {
+        slugs.reserve(category_slug);
+    }
+    let previous_slugs = previous_catalog
+        .iter()
+        .filter(|product| !product.slug.is_empty())
+        .map(|product| (product.id.as_str(), product.slug.as_str()))
+        .collect::<HashMap<_, _>>();
+    for slug in id_map.slugs().chain(previous_slugs.values().copied()) {
+        slugs.reserve(slug);
+    }
+    for product in products.iter_mut() {
+        let previous_slug = id_map
+            .slug(&product.id)
+            .or_else(|| previous_slugs.get(product.id.as_str()).copied());
+        product.slug = match previous_slug {
+            Some(slug) => {
+                if category_slugs.contains(slug) {
+                    printlnpb!(
+                        "Warning: product {} keeps its slug '{}', which is now also the slug of a category.",
+                        product.id,
+                        slug
+                    );
+                }
+                slug.to_owned()
+            }
+            None => slugs.slugify(&product.name, &format!("produto-{}", product.id)),
+        };
+        id_map.set_slug(&product.id, &product.slug);
+    }
+}
+
+// Old category pages are redirected to the category at the same level of the
+// new path of the product they were found at, or to the deepest one when the
+// new path is shorter.
+pub fn get_redirects(products: &[Product], category_tree: &CategoryTree) -> Vec<Redirect> {
+    let mut redirects = vec![];
+    let mut old_paths = HashSet::new();
+    for product in products {
+        if let Some(old_path) = url_path(&product.url) {
+            if old_paths.insert(old_path.clone()) {
+                redirects.push(Redirect {
+                    kind: "product",
+                    old_url: old_path,
+                    new_url: format!("/{}", product.slug),
+                });
+            }
+        }
+        if product.categories.is_empty() {
+            continue;
+        }
+        for (level, category_url) in product.category_urls.iter().enumerate() {
+            let depth = (level + 1).min(product.categories.len());
+            let (old_path, new_path) = match (
+                url_path(category_url),
+                category_tree.url_path(&product.categories[..depth]),
+            ) {
+                (Some(old_path), Some(new_path)) => (old_path, new_path),
+                _ => continue,
+            };
+            if old_paths.insert(old_path.clone()) {
+                redirects.push(Redirect {
+                    kind: "category",
+                    old_url: old_path,
+                    new_url: new_path,
+                });
+            }
+        }
+    }
+    redirects
+}
+
+// New path of each old product and category page.
+pub fn new_paths(redirects: Vec<Redirect>) -> HashMap<String, String> {
+    redirects
+        .into_iter()
+        .map(|redirect| (redirect.old_url, redirect.new_url))
+        .collect()
+}
+
+pub fn to_nginx_map(redirects: &[Redirect]) -> String {
+    let mut text = String::from(
+        "# Include in the http block and redirect in the server block with:\n\
+         # if ($iluria_redirect) { return 301 $iluria_redirect; }\n\
+         map $uri $iluria_redirect {\n",
+    );
+    for redirect in redirects {
+        text.push_str(&format!(
+            "    \"{}\" \"{}\";\n",
+            redirect.old_url, redirect.new_url
+        ));
+    }
+    text.push_str("}\n");
+    text
+}
+
+pub fn to_htaccess(redirects: &[Redirect]) -> String {
+    redirects
+        .iter()
+        .map(|redirect| {
+            format!(
+                "Redirect 301 \"{}\" \"{}\"\n",
+                redirect.old_url, redirect.new_url
+            )
+        })
+        .collect()
+}
+
+fn url_path(url: &str) -> Option<String> {
+    url::Url::parse(url)
+        .ok()
+        .map(|url| url.path().to_owned())
+        .filter(|path| !path.is_empty() && path != "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn product(id: &str, name: &str, categories: &[(&str, &str)]) -> Product {
+        Product::test(id)
+            .with_name(name)
+            .with_url(&format!("http://loja.com.br/pd-{}", id))
+            .with_categories(&categories.iter().map(|c| c.0).collect::<Vec<_>>())
+            .with_category_urls(
+                &categories
+                    .iter()
+                    .map(|c| format!("http://loja.com.br{}", c.1))
+                    .collect::<Vec<_>>(),
+            )
+    }
+
+    #[test]
+    fn redirects_products_and_categories_to_unique_slugs() {
+        let mut products = vec![
+            product("1", "Roupas", &[("Roupas", "/roupas-c1")]),
+            product(
+                "2",
+                "Camiseta",
+                &[("Roupas", "/roupas-c1"), ("Camisetas", "/camisetas-c2")],
+            ),
+            product("3", "Camiseta", &[]),
+        ];
+        let category_tree = CategoryTree::from_products(&products);
+        let mut id_map = IdMap::default();
+        id_map.assign_ids(&products);
+        assign_product_slugs(&mut products, &category_tree, &mut id_map, &[]);
+        let redirects = get_redirects(&products, &category_tree)
+            .into_iter()
+            .map(|r| (r.kind, r.old_url, r.new_url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("product", "/pd-1".to_owned(), "/roupas-2".to_owned()),
+                ("category", "/roupas-c1".to_owned(), "/roupas".to_owned()),
+                ("product", "/pd-2".to_owned(), "/camiseta".to_owned()),
+                (
+                    "category",
+                    "/camisetas-c2".to_owned(),
+                    "/roupas/camisetas".to_owned()
+                ),
+                ("product", "/pd-3".to_owned(), "/camiseta-2".to_owned()),
+            ],
+            redirects
+        );
+    }
+
+    #[test]
+    fn keeps_the_slugs_of_previous_runs() {
+        let category_tree = CategoryTree::default();
+        let mut id_map = IdMap::default();
+        let mut first_run = vec![product("2", "Camiseta", &[]), product("4", "Caneca", &[])];
+        id_map.assign_ids(&first_run);
+        assign_product_slugs(&mut first_run, &category_tree, &mut id_map, &[]);
+        let mut second_run = vec![
+            product("1", "Camiseta", &[]),
+            product("2", "Camiseta Branca", &[]),
+            product("3", "Caneca", &[]),
+        ];
+        id_map.assign_ids(&second_run);
+        assign_product_slugs(&mut second_run, &category_tree, &mut id_map, &[]);
+        assert_eq!(
+            vec!["camiseta-2", "camiseta", "caneca-2"],
+            second_run
+                .iter()
+                .map(|p| p.slug.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some("caneca"), id_map.slug("4"));
+    }
+
+    #[test]
+    fn keeps_the_slugs_of_the_previous_catalog() {
+        let mut previous_catalog = vec![product("2", "Camiseta", &[])];
+        previous_catalog[0].slug = "camiseta".to_owned();
+        let mut products = vec![product("1", "Camiseta", &[]), product("2", "Camiseta", &[])];
+        let mut id_map = IdMap::default();
+        id_map.assign_ids(&products);
+        assign_product_slugs(
+            &mut products,
+            &CategoryTree::default(),
+            &mut id_map,
+            &previous_catalog,
+        );
+        assert_eq!(
+            vec!["camiseta-2", "camiseta"],
+            products.iter().map(|p| p.slug.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(Some("camiseta"), id_map.slug("2"));
+    }
+}
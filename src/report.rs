@@ -0,0 +1,302 @@
+use crate::{
+    categories,
+    lint::{self, Problem, Severity},
+    money::{self, Locale},
+    run::{Availability, Product},
+};
+use std::{collections::BTreeMap, time::Duration};
+
+pub struct RunReport<'a> {
+    pub products: &'a [Product],
+    pub problems: &'a [Problem],
+    pub timings: &'a [(&'static str, Duration)],
+    pub locale: Locale,
+}
+
+enum Cell {
+    Text(String),
+    Link(String, String),
+    Image(String),
+}
+
+struct Section {
+    title: &'static str,
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl RunReport<'_> {
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Iluria export</title>\n<style>\n\
+             body { font-family: sans-serif; }\n\
+             table { border-collapse: collapse; margin-bottom: 2em; }\n\
+             th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n\
+             img { max-width: 80px; max-height: 80px; }\n\
+             </style>\n</head>\n<body>\n<h1>Iluria export</h1>\n",
+        );
+        for section in self.sections() {
+            html.push_str(&format!("<h2>{}</h2>\n", section.title));
+            if section.rows.is_empty() {
+                html.push_str("<p>None.</p>\n");
+                continue;
+            }
+            html.push_str("<table>\n<tr>");
+            for header in section.headers.iter() {
+                html.push_str(&format!("<th>{}</th>", header));
+            }
+            html.push_str("</tr>\n");
+            for row in section.rows.iter() {
+                html.push_str("<tr>");
+                for cell in row.iter() {
+                    let cell = match cell {
+                        Cell::Text(text) => escape_html(text),
+                        Cell::Link(text, url) if url.is_empty() => escape_html(text),
+                        Cell::Link(text, url) => {
+                            format!("<a href=\"{}\">{}</a>", escape_html(url), escape_html(text))
+                        }
+                        Cell::Image(url) if url.is_empty() => "".to_owned(),
+                        Cell::Image(url) => format!("<img src=\"{}\" alt=\"\">", escape_html(url)),
+                    };
+                    html.push_str(&format!("<td>{}</td>", cell));
+                }
+                html.push_str("</tr>\n");
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::from("# Iluria export\n");
+        for section in self.sections() {
+            markdown.push_str(&format!("\n## {}\n\n", section.title));
+            if section.rows.is_empty() {
+                markdown.push_str("None.\n");
+                continue;
+            }
+            markdown.push_str(&format!("| {} |\n", section.headers.join(" | ")));
+            markdown.push_str(&format!("|{}\n", " --- |".repeat(section.headers.len())));
+            for row in section.rows.iter() {
+                let cells = row
+                    .iter()
+                    .map(|cell| match cell {
+                        Cell::Text(text) => escape_markdown(text),
+                        Cell::Link(text, url) if url.is_empty() => escape_markdown(text),
+                        Cell::Link(text, url) => format!("[{}]({})", escape_markdown(text), url),
+                        Cell::Image(url) if url.is_empty() => "".to_owned(),
+                        Cell::Image(url) => format!("![]({})", url),
+                    })
+                    .collect::<Vec<_>>();
+                markdown.push_str(&format!("| {} |\n", cells.join(" | ")));
+            }
+        }
+        markdown
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let text = |value: &dyn ToString| Cell::Text(value.to_string());
+        let link = |product: &Product| Cell::Link(product.name.clone(), product.url.clone());
+        let categories = count_by(self.products, categories::category_path);
+        let suppliers = count_by(self.products, |p| p.vendor_name.trim().to_owned());
+        let products_without = |missing: fn(&Product) -> bool| {
+            self.products
+                .iter()
+                .filter(|p| missing(p))
+                .map(|p| vec![text(&p.id), link(p)])
+                .collect()
+        };
+        vec![
+            Section {
+                title: "Summary",
+                headers: vec!["", "Total"],
+                rows: vec![
+                    vec![text(&"Products"), text(&self.products.len())],
+                    vec![
+                        text(&"Variations"),
+                        text(
+                            &self
+                                .products
+                                .iter()
+                                .map(|p| p.variations.len())
+                                .sum::<usize>(),
+                        ),
+                    ],
+                    vec![text(&"Categories"), text(&categories.len())],
+                    vec![text(&"Suppliers"), text(&suppliers.len())],
+                    vec![
+                        text(&"Inactive products"),
+                        text(&self.products.iter().filter(|p| !p.is_active()).count()),
+                    ],
+                    vec![
+                        text(&"Errors"),
+                        text(&lint::count(self.problems, Severity::Error)),
+                    ],
+                    vec![
+                        text(&"Warnings"),
+                        text(&lint::count(self.problems, Severity::Warning)),
+                    ],
+                ],
+            },
+            Section {
+                title: "Timings",
+                headers: vec!["Step", "Seconds"],
+                rows: self
+                    .timings
+                    .iter()
+                    .map(|(step, duration)| {
+                        vec![text(step), text(&format!("{:.1}", duration.as_secs_f64()))]
+                    })
+                    .collect(),
+            },
+            Section {
+                title: "Errors and warnings",
+                headers: vec!["Product", "Name", "Severity", "Rule", "Message"],
+                rows: self
+                    .problems
+                    .iter()
+                    .map(|p| {
+                        vec![
+                            text(&p.product_id),
+                            Cell::Link(p.product_name.clone(), p.url.clone()),
+                            text(&format!("{:?}", p.severity).to_lowercase()),
+                            text(&p.rule),
+                            text(&p.message),
+                        ]
+                    })
+                    .collect(),
+            },
+            Section {
+                title: "Inactive products",
+                headers: vec!["Product", "Name", "Reason"],
+                rows: self
+                    .products
+                    .iter()
+                    .filter(|p| !p.is_active())
+                    .map(|p| {
+                        let reason = match p.availability {
+                            Availability::NotFound => "Page not found",
+                            Availability::Unavailable => "Unavailable",
+                            Availability::Available => "Out of stock",
+                        };
+                        vec![text(&p.id), link(p), text(&reason)]
+                    })
+                    .collect(),
+            },
+            Section {
+                title: "Products without pictures",
+                headers: vec!["Product", "Name"],
+                rows: products_without(|p| p.pictures.is_empty()),
+            },
+            Section {
+                title: "Products without description",
+                headers: vec!["Product", "Name"],
+                rows: products_without(|p| p.description.trim().is_empty()),
+            },
+            Section {
+                title: "Categories",
+                headers: vec!["Category", "Products"],
+                rows: categories
+                    .into_iter()
+                    .map(|(category, products)| vec![text(&category), text(&products)])
+                    .collect(),
+            },
+            Section {
+                title: "Suppliers",
+                headers: vec!["Supplier", "Products"],
+                rows: suppliers
+                    .into_iter()
+                    .map(|(supplier, products)| vec![text(&supplier), text(&products)])
+                    .collect(),
+            },
+            Section {
+                title: "Products",
+                headers: vec![
+                    "",
+                    "Product",
+                    "Name",
+                    "Price",
+                    "Variations",
+                    "Category",
+                    "Supplier",
+                ],
+                rows: self
+                    .products
+                    .iter()
+                    .map(|p| {
+                        vec![
+                            Cell::Image(p.pictures.first().cloned().unwrap_or_default()),
+                            text(&p.id),
+                            link(p),
+                            text(&money::format(p.price, self.locale)),
+                            text(&p.variations.len()),
+                            text(&categories::category_path(p)),
+                            text(&p.vendor_name),
+                        ]
+                    })
+                    .collect(),
+            },
+        ]
+    }
+}
+
+fn count_by(products: &[Product], key: impl Fn(&Product) -> String) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for product in products {
+        *counts.entry(key(product)).or_insert(0) += 1;
+    }
+    counts.remove("");
+    counts
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn writes_markdown_report_with_links_and_thumbnails() {
+        let products = vec![Product {
+            id: "1".to_owned(),
+            url: "http://loja.com.br/pd-1".to_owned(),
+            name: "Café | Moído".to_owned(),
+            price: Money::from_cents(1050),
+            vendor_name: "Fornecedor".to_owned(),
+            description: "Café".to_owned(),
+            pictures: vec!["http://loja.com.br/1.jpg".to_owned()],
+            ..Default::default()
+        }];
+        let report = RunReport {
+            products: &products,
+            problems: &[],
+            timings: &[("Total", Duration::from_millis(1500))],
+            locale: Locale::PtBr,
+        };
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| Variations | 0 |\n"));
+        assert!(markdown.contains("| Total | 1.5 |\n"));
+        assert!(markdown.contains("## Products without pictures\n\nNone.\n"));
+        assert!(markdown.contains("| Fornecedor | 1 |\n"));
+        assert_eq!(
+            Some("| ![](http://loja.com.br/1.jpg) | 1 | [Café \\| Moído](http://loja.com.br/pd-1) | R$ 10,50 | 0 |  | Fornecedor |"),
+            markdown.lines().last()
+        );
+        assert!(report
+            .to_html()
+            .contains("<td><a href=\"http://loja.com.br/pd-1\">Café | Moído</a></td>"));
+    }
+}
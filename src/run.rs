@@ -1,67 +1,463 @@
-use crate::{args::Args, enricher, exporter, importer, progressbar};
+use crate::shipping::Shipping;
+use crate::{
+    args::Args, categories, description, enricher, exporter, filter, ids, importer, lint,
+    money::Money, pictures, pricing, progressbar, redirects, report, shipping, stock, sync,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 pub fn run(args: Args) -> Result<(), Option<String>> {
     printlnv!("Starting...");
+    let start = Instant::now();
+    let price_rules = match &args.price_rules_file {
+        Some(file) => Some(pricing::load_rules(file, args.locale)?),
+        None => None,
+    };
+    let lint_rules = match &args.lint_rules_file {
+        Some(file) => lint::load_rules(file)?,
+        None => lint::LintRules::default(),
+    };
+    let description_rules = match &args.description_rules_file {
+        Some(file) => description::load_rules(file)?,
+        None => description::DescriptionRules::default(),
+    };
+    let shipping_defaults = match &args.shipping_defaults_file {
+        Some(file) => Some(shipping::load_defaults(file)?),
+        None => None,
+    };
     progressbar::start_progress_bar(100);
-    let products_with_variation = importer::get_products_with_variations(&args.file)?;
-    progressbar::inc_progress_bar(10);
-    let mut products = get_products_from_variations(products_with_variation, args.limit);
-    progressbar::inc_progress_bar(10);
+    let category_mappings = match &args.category_map_file {
+        Some(file) => Some(categories::load_mappings(file)?),
+        None => None,
+    };
+    let mut id_map = match &args.id_map_file {
+        Some(file) => ids::IdMap::load(file)?,
+        None => ids::IdMap::default(),
+    };
+    if args.stock_update {
+        let rows = importer::get_products_with_variations(&args.file, args.locale)?;
+        let stock_updates = stock::get_stock_updates(
+            rows,
+            args.limit,
+            &mut id_map,
+            price_rules.as_deref(),
+            args.locale,
+        )?;
+        exporter::save_stock_updates_to_file(
+            stock_updates,
+            args.get_output_file(&args.output_stock_file),
+        )?;
+        exporter::save_id_map_to_file(id_map, args.get_output_file(&args.output_id_map_file))?;
+        progressbar::finish_progress_bar();
+        printlnv!("Done!");
+        return Ok(());
+    }
+    let previous_catalog = match &args.previous_catalog_file {
+        Some(file) => Some(sync::load_catalog(file)?),
+        None => None,
+    };
+    let selection = filter::Selection {
+        only_ids: args.only_ids.clone(),
+        filter: args.filter.as_deref().map(filter::parse).transpose()?,
+        offset: args.offset,
+        limit: args.limit,
+        sample: args.sample,
+        seed: args.seed,
+    };
+    let products_with_variation = importer::get_products_with_variations(&args.file, args.locale)?;
+    let mut products = get_products_from_variations(products_with_variation, &selection)?;
+    let mut timings = vec![("Reading", start.elapsed())];
+    progressbar::inc_progress_bar(20);
     progressbar::set_progress_bar_len((products.len() as f64 / 0.8).round() as u64);
-    enricher::enrich_products(&args.url, &mut products, args.simulate)?;
-    let (products_file, variations_file) = args.get_output_files();
-    exporter::save_enriched_products_to_file(products, products_file, variations_file)?;
+    let step_start = Instant::now();
+    enricher::enrich_products(
+        &args.url,
+        &mut products,
+        args.source.product_source().as_ref(),
+        args.simulate,
+        args.fixtures_dir.as_deref(),
+        args.timeout,
+        args.locale,
+    )?;
+    timings.push(("Scraping", step_start.elapsed()));
+    if selection.filter.is_some() {
+        products.retain(|product| selection.accepts_enriched(product));
+        printlnv!("{} products left after filtering.", products.len());
+    }
+    let inactive = products.iter().filter(|p| !p.is_active()).count();
+    if inactive > 0 {
+        printlnpb!("{} products will be exported as inactive.", inactive);
+    }
+    let step_start = Instant::now();
+    let categories_found = categories::map_categories(category_mappings.as_deref(), &mut products);
+    if let Some(shipping_defaults) = shipping_defaults {
+        shipping::apply_defaults(&shipping_defaults, &mut products);
+    }
+    if let Some(price_rules) = price_rules {
+        pricing::apply_rules(&price_rules, &mut products, args.locale);
+    }
+    id_map.assign_ids(&products);
+    let category_tree = categories::CategoryTree::from_products(&products);
+    redirects::assign_product_slugs(
+        &mut products,
+        &category_tree,
+        &mut id_map,
+        previous_catalog.as_deref().unwrap_or_default(),
+    );
+    let new_images = match &args.pictures_dir {
+        Some(dir) => {
+            let step_start = Instant::now();
+            let new_images = pictures::download_pictures(
+                &products,
+                dir,
+                args.pictures_url.as_deref().unwrap_or_default(),
+                args.simulate,
+            )?;
+            timings.push(("Pictures", step_start.elapsed()));
+            pictures::move_pictures(&new_images, &mut products);
+            new_images
+        }
+        None => HashMap::new(),
+    };
+    description::clean_descriptions(
+        &description_rules,
+        args.description_format,
+        &args.url,
+        args.new_url.as_deref(),
+        &redirects::new_paths(redirects::get_redirects(&products, &category_tree)),
+        &new_images,
+        &mut products,
+    )?;
+    let problems = lint::lint_products(&lint_rules, &products);
+    let errors = lint::count(&problems, lint::Severity::Error);
+    printlnpb!(
+        "Validation found {} errors and {} warnings.",
+        errors,
+        lint::count(&problems, lint::Severity::Warning)
+    );
+    exporter::save_lint_report_to_file(&problems, args.get_output_file(&args.output_lint_file))?;
+    if args.strict && errors > 0 {
+        timings.push(("Total", start.elapsed()));
+        save_run_report(&args, &products, &problems, &timings)?;
+        progressbar::finish_progress_bar();
+        return Err(Some(format!(
+            "Validation found {} errors, nothing was exported.",
+            errors
+        )));
+    }
+    if args.redirects {
+        exporter::save_redirects_to_files(
+            redirects::get_redirects(&products, &category_tree),
+            args.get_output_file(&args.output_redirects_files.0),
+            args.get_output_file(&args.output_redirects_files.1),
+            args.get_output_file(&args.output_redirects_files.2),
+        )?;
+    }
+    let picture_layout = exporter::PictureLayout::for_products(
+        args.picture_columns,
+        args.pictures_separator.clone(),
+        &products,
+    );
+    let catalog = sync::catalog_to_json(&products)?;
+    let changes = previous_catalog.map(|previous| sync::diff(previous, &products));
+    if let Some(changes) = &changes {
+        id_map.assign_ids(&changes.deleted);
+    }
+    let export_context = exporter::ExportContext {
+        category_tree: &category_tree,
+        id_map: &id_map,
+        picture_layout: &picture_layout,
+        new_store_url: args.new_url.as_deref(),
+    };
+    match changes {
+        Some(changes) => {
+            exporter::save_changes_to_files(
+                &changes,
+                &export_context,
+                args.get_sync_output_files("create"),
+                args.get_sync_output_files("update"),
+                args.get_sync_output_files("delete"),
+                args.get_output_file(&args.output_changes_file),
+            )?;
+            printlnpb!("{}", changes.summary());
+        }
+        None => {
+            let (products_file, variations_file) = args.get_output_files();
+            exporter::save_enriched_products_to_file(
+                &products,
+                &export_context,
+                products_file,
+                variations_file,
+            )?;
+        }
+    }
+    exporter::save_catalog_to_file(catalog, args.get_output_file(&args.output_catalog_file))?;
+    exporter::save_categories_to_file(
+        category_tree,
+        args.get_output_file(&args.output_categories_file),
+    )?;
+    exporter::save_id_map_to_file(id_map, args.get_output_file(&args.output_id_map_file))?;
+    exporter::save_categories_found_to_file(
+        categories_found,
+        args.get_output_file(&args.output_iluria_categories_file),
+    )?;
+    timings.push(("Exporting", step_start.elapsed()));
+    timings.push(("Total", start.elapsed()));
+    save_run_report(&args, &products, &problems, &timings)?;
     progressbar::finish_progress_bar();
     printlnv!("Done!");
     Ok(())
 }
 
-fn get_products_from_variations(
-    products_with_variation: Vec<importer::ProductWithVariation>,
-    limit: u32,
-) -> Vec<Product> {
-    products_with_variation
-        .into_iter()
-        .fold(vec![], |mut ps, product_with_variation| {
-            let product_id = product_with_variation.produto;
-            if ps.iter().find(|p| p.id == product_id).is_none()
-                && (limit == 0 || (ps.len() as u32) < limit)
-            {
-                // todo: work around usize limit in products, see ps.len above
-                ps.push(Product {
-                    id: product_id,
-                    name: product_with_variation.nome,
-                    variations: vec![],
-                    stock: product_with_variation.estoque,
-                    price: product_with_variation.preco,
-                    price_cost: product_with_variation.preco_de_custo,
-                    vendor_name: product_with_variation.nome_do_fornecedor,
-                    description: "".to_owned(),
-                    category: "".to_owned(),
-                    subcategory: "".to_owned(),
-                    pictures: vec![],
-                });
+fn save_run_report(
+    args: &Args,
+    products: &[Product],
+    problems: &[lint::Problem],
+    timings: &[(&'static str, Duration)],
+) -> Result<(), String> {
+    exporter::save_run_report_to_files(
+        &report::RunReport {
+            products,
+            problems,
+            timings,
+            locale: args.locale,
+        },
+        args.get_output_file(&args.output_report_files.0),
+        args.get_output_file(&args.output_report_files.1),
+    )
+}
+
+// Codes of a variation row are kept in a variation with only the name of the
+// report, until the page of the product has the actual variations.
+fn add_report_variation(product: &mut Product, row: importer::ProductWithVariation) {
+    if row.variacao.trim().is_empty() {
+        if !row.sku.is_empty() {
+            product.sku = row.sku;
+        }
+        if !row.gtin.is_empty() {
+            product.gtin = row.gtin;
+        }
+    } else if !row.sku.is_empty() || !row.gtin.is_empty() {
+        product.variations.push(Variation {
+            name1: row.variacao,
+            sku: row.sku,
+            gtin: row.gtin,
+            ..Default::default()
+        });
+    }
+    if product.brand.is_empty() {
+        product.brand = row.marca;
+    }
+}
+
+/// Groups the rows of the report by product id, keeping the order in which the
+/// products first appear, and keeps the ones the selection accepts. Reading
+/// stops as soon as `limit` products were found (0 means no limit), so the rest
+/// of the file is never decoded.
+fn get_products_from_variations<I>(
+    products_with_variation: I,
+    selection: &filter::Selection,
+) -> Result<Vec<Product>, String>
+where
+    I: IntoIterator<Item = Result<importer::ProductWithVariation, String>>,
+{
+    let mut products = vec![];
+    // Position of each product read, None for the ones left out.
+    let mut positions: HashMap<String, Option<usize>> = HashMap::new();
+    let mut skipped = 0;
+    for product_with_variation in products_with_variation {
+        let product_with_variation = product_with_variation?;
+        if let Some(position) = positions.get(&product_with_variation.produto) {
+            if let Some(&position) = position.as_ref() {
+                add_report_variation(&mut products[position], product_with_variation);
             }
-            ps
-        })
+            continue;
+        }
+        if selection.limit != 0 && selection.sample.is_none() && products.len() >= selection.limit {
+            break;
+        }
+        let mut product = Product {
+            id: product_with_variation.produto.clone(),
+            name: product_with_variation.nome.clone(),
+            stock: product_with_variation.estoque,
+            price: product_with_variation.preco,
+            price_cost: product_with_variation.preco_de_custo,
+            vendor_name: product_with_variation.nome_do_fornecedor.clone(),
+            shipping: product_with_variation.frete.clone(),
+            handle: product_with_variation.handle.clone(),
+            ..Default::default()
+        };
+        add_report_variation(&mut product, product_with_variation);
+        if !selection.accepts_imported(&product) {
+            positions.insert(product.id, None);
+            continue;
+        }
+        if skipped < selection.offset {
+            skipped += 1;
+            positions.insert(product.id, None);
+            continue;
+        }
+        positions.insert(product.id.clone(), Some(products.len()));
+        products.push(product);
+    }
+    Ok(selection.sample(products))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Product {
     pub id: String,
+    pub url: String,
+    pub slug: String,
     pub name: String,
     pub variations: Vec<Variation>,
     pub stock: Option<u32>,
-    pub price: f64,
-    pub price_cost: Option<f64>,
+    pub price: Money,
+    pub price_cost: Option<Money>,
     pub vendor_name: String,
     pub description: String,
-    pub category: String,
-    pub subcategory: String,
+    pub categories: Vec<String>,
+    pub category_urls: Vec<String>,
     pub pictures: Vec<String>,
+    #[serde(default)]
+    pub availability: Availability,
+    #[serde(default)]
+    pub promotion: Option<Promotion>,
+    #[serde(default)]
+    pub installments: Option<Installments>,
+    #[serde(default)]
+    pub shipping: Shipping,
+    #[serde(default)]
+    pub seo: Seo,
+    #[serde(default)]
+    pub sku: String,
+    #[serde(default)]
+    pub gtin: String,
+    #[serde(default)]
+    pub brand: String,
+    #[serde(default)]
+    pub handle: String,
+}
+
+impl Product {
+    // Products without stock control (no stock in the report) stay active.
+    pub fn is_active(&self) -> bool {
+        self.availability == Availability::Available && self.stock != Some(0)
+    }
+}
+
+// Products for tests, named after their id, with only what each test checks.
+#[cfg(test)]
+impl Product {
+    pub fn test(id: &str) -> Product {
+        Product {
+            id: id.to_owned(),
+            name: format!("Product {}", id),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_name(self, name: &str) -> Product {
+        Product {
+            name: name.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn with_url(self, url: &str) -> Product {
+        Product {
+            url: url.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn with_price(self, cents: i64) -> Product {
+        Product {
+            price: Money::from_cents(cents),
+            ..self
+        }
+    }
+
+    pub fn with_cost(self, cents: Option<i64>) -> Product {
+        Product {
+            price_cost: cents.map(Money::from_cents),
+            ..self
+        }
+    }
+
+    pub fn with_vendor(self, vendor: &str) -> Product {
+        Product {
+            vendor_name: vendor.to_owned(),
+            ..self
+        }
+    }
+
+    pub fn with_categories(self, categories: &[&str]) -> Product {
+        Product {
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+            ..self
+        }
+    }
+
+    pub fn with_category_urls(self, urls: &[String]) -> Product {
+        Product {
+            category_urls: urls.to_vec(),
+            ..self
+        }
+    }
+
+    pub fn with_pictures(self, pictures: &[&str]) -> Product {
+        Product {
+            pictures: pictures.iter().map(|p| p.to_string()).collect(),
+            ..self
+        }
+    }
+
+    pub fn with_variations(self, variations: Vec<Variation>) -> Product {
+        Product { variations, ..self }
+    }
+
+    pub fn with_shipping(self, shipping: Shipping) -> Product {
+        Product { shipping, ..self }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Availability {
+    #[default]
+    Available,
+    Unavailable,
+    NotFound,
+}
+
+// The price of a product is its regular price, the promotion has the price it
+// is sold for while the promotion lasts. Dates are kept as shown (dd/mm/yyyy).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Promotion {
+    pub price: Money,
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+// What the page tells search engines, kept so the new store ranks the same.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Seo {
+    pub title: String,
+    pub description: String,
+    pub keywords: String,
+    pub canonical_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Installments {
+    pub count: u32,
+    pub value: Money,
+    pub interest_free: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Variation {
     pub type1: String,
     pub type2: Option<String>,
@@ -69,6 +465,116 @@ pub struct Variation {
     pub name1: String,
     pub name2: Option<String>,
     pub name3: Option<String>,
-    pub price: f64,
+    pub price: Money,
+    #[serde(default)]
+    pub promotional_price: Option<Money>,
     pub picture: Option<String>,
+    #[serde(default)]
+    pub sku: String,
+    #[serde(default)]
+    pub gtin: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn row(id: &str) -> Result<importer::ProductWithVariation, String> {
+        Ok(importer::ProductWithVariation {
+            produto: id.to_owned(),
+            nome: format!("Product {}", id),
+            variacao: "".to_owned(),
+            estoque: Some(1),
+            preco: Money::from_cents(1000),
+            preco_de_custo: None,
+            nome_do_fornecedor: "".to_owned(),
+            frete: Default::default(),
+            sku: "".to_owned(),
+            gtin: "".to_owned(),
+            marca: "".to_owned(),
+            handle: "".to_owned(),
+        })
+    }
+
+    fn ids(products: &[Product]) -> Vec<&str> {
+        products.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    #[test]
+    fn groups_rows_by_product_keeping_first_appearance_order() -> Result<(), String> {
+        let rows = vec![row("3"), row("1"), row("3"), row("2"), row("1")];
+        let products = get_products_from_variations(rows, &Default::default())?;
+        assert_eq!(vec!["3", "1", "2"], ids(&products));
+        Ok(())
+    }
+
+    #[test]
+    fn stops_reading_when_limit_is_reached() -> Result<(), String> {
+        let rows = vec![row("1"), row("1"), row("2"), Err("not read".to_owned())];
+        let selection = filter::Selection {
+            limit: 1,
+            ..Default::default()
+        };
+        let products = get_products_from_variations(rows, &selection)?;
+        assert_eq!(vec!["1"], ids(&products));
+        Ok(())
+    }
+
+    #[test]
+    fn skips_products_left_out_by_ids_and_offset() -> Result<(), String> {
+        let rows = vec![row("1"), row("2"), row("3"), row("4"), row("3")];
+        let selection = filter::Selection {
+            only_ids: Some(["2", "3", "4"].iter().map(|id| id.to_string()).collect()),
+            offset: 1,
+            limit: 1,
+            ..Default::default()
+        };
+        let products = get_products_from_variations(rows, &selection)?;
+        assert_eq!(vec!["3"], ids(&products));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_codes_of_the_report_rows() -> Result<(), String> {
+        let code_row = |variation: &str, sku: &str| {
+            row("1").map(|row| importer::ProductWithVariation {
+                variacao: variation.to_owned(),
+                sku: sku.to_owned(),
+                marca: "Marca".to_owned(),
+                ..row
+            })
+        };
+        let rows = vec![
+            code_row("", "SKU-1"),
+            code_row("P", "SKU-1-P"),
+            row("2"),
+            code_row("M", ""),
+            code_row("G", "SKU-1-G"),
+            code_row("", ""),
+        ];
+        let products = get_products_from_variations(rows, &Default::default())?;
+        assert_eq!(
+            ("SKU-1", "Marca"),
+            (products[0].sku.as_str(), products[0].brand.as_str())
+        );
+        assert_eq!(
+            vec![("P", "SKU-1-P"), ("G", "SKU-1-G")],
+            products[0]
+                .variations
+                .iter()
+                .map(|v| (v.name1.as_str(), v.sku.as_str()))
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_row_errors() {
+        let rows = vec![row("1"), Err("bad row".to_owned())];
+        assert_eq!(
+            "bad row",
+            get_products_from_variations(rows, &Default::default()).unwrap_err()
+        );
+    }
 }
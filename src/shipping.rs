@@ -0,0 +1,170 @@
+use crate::{
+    categories,
+    money::{self, Locale},
+    run::Product,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs};
+
+// Weight in grams and dimensions in centimeters, which Tray needs to calculate
+// shipping. Tray refuses zeros, so they count as missing.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Shipping {
+    pub weight: Option<u32>,
+    pub height: Option<u32>,
+    pub width: Option<u32>,
+    pub length: Option<u32>,
+}
+
+impl Shipping {
+    // Keeps the values already set, taking only the missing ones.
+    pub fn fill_from(&mut self, other: &Shipping) {
+        self.weight = known(self.weight).or(known(other.weight));
+        self.height = known(self.height).or(known(other.height));
+        self.width = known(self.width).or(known(other.width));
+        self.length = known(self.length).or(known(other.length));
+    }
+
+    pub fn missing(&self) -> Vec<&'static str> {
+        [
+            ("weight", self.weight),
+            ("height", self.height),
+            ("width", self.width),
+            ("length", self.length),
+        ]
+        .iter()
+        .filter(|(_, value)| known(*value).is_none())
+        .map(|(name, _)| *name)
+        .collect()
+    }
+}
+
+fn known(value: Option<u32>) -> Option<u32> {
+    value.filter(|value| *value > 0)
+}
+
+// Measures are whole numbers, in the number format of the locale, like
+// "1.250" grams in pt-BR, but "12,5" is rounded instead of refused. Zeros are
+// missing measures.
+pub fn parse_measure(text: &str, locale: Locale) -> Result<Option<u32>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let invalid = || format!("Invalid measure '{}'.", text);
+    let value = money::parse(text, locale).map_err(|_| invalid())?;
+    let units = (value.cents() + 50) / 100;
+    if value.cents() < 0 || units > i64::from(u32::MAX) {
+        return Err(invalid());
+    }
+    Ok(known(Some(units as u32)))
+}
+
+// Shipping data for products that have none, from a TOML file like:
+// [default]
+// weight = 300
+// [categories."Roupas > Camisetas"]
+// weight = 200
+// height = 5
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShippingDefaults {
+    default: Shipping,
+    categories: BTreeMap<String, Shipping>,
+}
+
+pub fn load_defaults(file: &str) -> Result<ShippingDefaults, String> {
+    let contents = fs::read_to_string(file).map_err(|e| {
+        format!(
+            "Error when opening shipping defaults file '{}': {}",
+            file, e
+        )
+    })?;
+    let defaults: ShippingDefaults = toml::from_str(&contents)
+        .map_err(|e| format!("Could not read shipping defaults file '{}': {}", file, e))?;
+    printlnv!("Shipping defaults: {:?}", defaults);
+    Ok(defaults)
+}
+
+// The most specific category comes first: the whole path, then each category
+// from the deepest one, then the defaults for every product.
+pub fn apply_defaults(defaults: &ShippingDefaults, products: &mut [Product]) {
+    let categories = defaults
+        .categories
+        .iter()
+        .map(|(category, shipping)| (category.trim().to_lowercase(), shipping))
+        .collect::<BTreeMap<_, _>>();
+    for product in products.iter_mut() {
+        let before = product.shipping.clone();
+        let path = categories::category_path(product);
+        for category in std::iter::once(&path).chain(product.categories.iter().rev()) {
+            if let Some(shipping) = categories.get(&category.trim().to_lowercase()) {
+                product.shipping.fill_from(shipping);
+            }
+        }
+        product.shipping.fill_from(&defaults.default);
+        if product.shipping != before {
+            printlnv!(
+                "Shipping defaults used for product {}: {:?}",
+                product.id,
+                product.shipping
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn fills_missing_shipping_data_from_the_most_specific_category() {
+        let defaults: ShippingDefaults = toml::from_str(
+            "[default]\nweight = 500\nheight = 10\nwidth = 10\nlength = 10\n\
+             [categories.roupas]\nweight = 300\nheight = 5\n\
+             [categories.\"Roupas > Camisetas\"]\nweight = 200\n",
+        )
+        .unwrap();
+        let product = |categories: &[&str], weight: Option<u32>| {
+            Product::test("1")
+                .with_categories(categories)
+                .with_shipping(Shipping {
+                    weight,
+                    ..Default::default()
+                })
+        };
+        let mut products = vec![
+            product(&["Roupas", "Camisetas"], None),
+            product(&["Roupas", "Calças"], None),
+            product(&["Casa"], Some(1000)),
+        ];
+        apply_defaults(&defaults, &mut products);
+        let shipping = |weight, height| Shipping {
+            weight: Some(weight),
+            height: Some(height),
+            width: Some(10),
+            length: Some(10),
+        };
+        assert_eq!(shipping(200, 5), products[0].shipping);
+        assert_eq!(shipping(300, 5), products[1].shipping);
+        assert_eq!(shipping(1000, 10), products[2].shipping);
+        assert_eq!(Ok(Some(13)), parse_measure(" 12,5 ", Locale::PtBr));
+        assert_eq!(Ok(Some(1250)), parse_measure("1.250", Locale::PtBr));
+        assert_eq!(Ok(Some(1251)), parse_measure("1.250,5", Locale::PtBr));
+        assert_eq!(Ok(Some(1)), parse_measure("1.250", Locale::EnUs));
+        assert_eq!(Ok(None), parse_measure("", Locale::PtBr));
+        assert_eq!(Ok(None), parse_measure("0", Locale::PtBr));
+        assert!(parse_measure("12 cm", Locale::PtBr).is_err());
+        assert!(parse_measure("-1", Locale::PtBr).is_err());
+        assert_eq!(
+            vec!["weight", "height", "width", "length"],
+            Shipping {
+                weight: Some(0),
+                ..Default::default()
+            }
+            .missing()
+        );
+    }
+}
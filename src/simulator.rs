@@ -0,0 +1,188 @@
+use crate::{
+    money::{self, Locale, Money},
+    report::escape_html,
+    run::Product,
+};
+use std::{fs, path::Path};
+
+const PRODUCT_PAGE: &str = include_str!("fixtures/product.html");
+
+// Page served in place of the Iluria product page when simulating. A page
+// saved as <id>.html in the fixtures directory is served as is. Otherwise a
+// page is generated from a template, the product.html file of the fixtures
+// directory or the bundled one, with variations, pictures, promotions and shipping data that
+// depend only on the product id, so every simulation of a product gets the same page.
+pub fn get_product_page(
+    product: &Product,
+    fixtures_dir: Option<&str>,
+    locale: Locale,
+) -> Result<String, String> {
+    if let Some(page) = get_recorded_page(product, fixtures_dir)? {
+        return Ok(page);
+    }
+    let template = match fixtures_dir {
+        Some(dir) => {
+            let template = Path::new(dir).join("product.html");
+            if template.is_file() {
+                read(&template)?
+            } else {
+                PRODUCT_PAGE.to_owned()
+            }
+        }
+        None => PRODUCT_PAGE.to_owned(),
+    };
+    Ok(generate_page(&template, product, locale))
+}
+
+pub fn get_recorded_page(
+    product: &Product,
+    fixtures_dir: Option<&str>,
+) -> Result<Option<String>, String> {
+    let page = match fixtures_dir {
+        Some(dir) => Path::new(dir).join(format!("{}.html", product.id)),
+        None => return Ok(None),
+    };
+    if page.is_file() {
+        printlnv!("Simulating with page '{}'.", page.display());
+        read(&page).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+fn read(file: &Path) -> Result<String, String> {
+    fs::read_to_string(file).map_err(|e| {
+        format!(
+            "Error when opening fixture file '{}': {}",
+            file.display(),
+            e
+        )
+    })
+}
+
+fn generate_page(template: &str, product: &Product, locale: Locale) -> String {
+    let seed = product.id.bytes().map(usize::from).sum::<usize>();
+    let picture_url = |n: usize| format!("//img.simulacao.iluria.com/{}/{}.jpg", product.id, n);
+    let breadcrumb = format!(
+        "<a href=\"/simulacao-c1\">Simulação</a>\n  <a href=\"/categoria-{0}-c{0}0\">Categoria {0}</a>",
+        seed % 3 + 1
+    );
+    let pictures = (1..=seed % 4 + 1)
+        .map(|n| format!("<img src=\"{0}\" mainpictureurl=\"{0}\">", picture_url(n)))
+        .collect::<Vec<_>>()
+        .join("\n    ");
+    // Some products are on sale, for 20% less than their regular price.
+    let on_sale = seed % 4 == 1;
+    let sale_price = |price: Money| {
+        if on_sale {
+            price.add_percentage(-20.0)
+        } else {
+            price
+        }
+    };
+    let price = sale_price(product.price);
+    let (original_price, promotion_period) = if on_sale {
+        (
+            format!(
+                "<div class=\"product-price-original\">{}</div>",
+                escape_html(&money::format(product.price, locale))
+            ),
+            "<div class=\"product-promotion-period\">Promoção válida até 31/12/2099</div>"
+                .to_owned(),
+        )
+    } else {
+        (String::new(), String::new())
+    };
+    let installments = format!(
+        "em até 2x de {} sem juros",
+        escape_html(&money::format(price.add_percentage(-50.0), locale))
+    );
+    // Some products have no weight and dimensions.
+    let shipping_inputs = if seed % 5 == 0 {
+        String::new()
+    } else {
+        [
+            ("productWeight", 100 * (seed % 5)),
+            ("productHeight", 2 * (seed % 5)),
+            ("productWidth", 15),
+            ("productLength", 20),
+        ]
+        .iter()
+        .map(|(id, value)| format!("<input type=\"hidden\" id=\"{}\" value=\"{}\">", id, value))
+        .collect::<Vec<_>>()
+        .join("\n    ")
+    };
+    let sizes = ["P", "M", "G"];
+    let colors = ["Azul", "Preto"];
+    let (selects, inputs) = match seed % 3 {
+        0 => (String::new(), String::new()),
+        kind => {
+            let mut selects = select(1, "Tamanho", &sizes);
+            let mut inputs = vec![];
+            let colors = if kind == 2 {
+                selects.push_str(&select(2, "Cor", &colors));
+                colors.iter().map(Some).enumerate().collect::<Vec<_>>()
+            } else {
+                vec![(0, None)]
+            };
+            for (size_position, _) in sizes.iter().enumerate() {
+                for (color_position, color) in colors.iter() {
+                    // The biggest size costs a little more.
+                    let price = if size_position == sizes.len() - 1 {
+                        product.price.add_percentage(10.0)
+                    } else {
+                        product.price
+                    };
+                    inputs.push(format!(
+                        "<input type=\"hidden\" class=\"allVariations\" value1=\"{}\" value2=\"{}\" convertedprice=\"{}\"{}{}>",
+                        size_position + 1,
+                        color.map(|_| (color_position + 1).to_string()).unwrap_or_default(),
+                        escape_html(&money::format(sale_price(price), locale)),
+                        if on_sale {
+                            format!(
+                                " convertedoriginalprice=\"{}\"",
+                                escape_html(&money::format(price, locale))
+                            )
+                        } else {
+                            String::new()
+                        },
+                        if size_position == 0 {
+                            format!(" mainpictureurl550=\"{}\"", picture_url(1))
+                        } else {
+                            String::new()
+                        }
+                    ));
+                }
+            }
+            (selects, inputs.join("\n    "))
+        }
+    };
+    template
+        .replace("{{id}}", &escape_html(&product.id))
+        .replace("{{name}}", &escape_html(&product.name))
+        .replace("{{original_price}}", &original_price)
+        .replace("{{price}}", &escape_html(&money::format(price, locale)))
+        .replace("{{promotion_period}}", &promotion_period)
+        .replace("{{installments}}", &installments)
+        .replace("{{shipping_inputs}}", &shipping_inputs)
+        .replace("{{breadcrumb}}", &breadcrumb)
+        .replace("{{pictures}}", &pictures)
+        .replace("{{variation_selects}}", &selects)
+        .replace("{{variation_inputs}}", &inputs)
+}
+
+fn select(number: usize, name: &str, options: &[&str]) -> String {
+    let mut select = format!(
+        "<select id=\"iluria-product-variation{}\">\n      <option value=\"0\">{}</option>\n",
+        number, name
+    );
+    for (position, option) in options.iter().enumerate() {
+        select.push_str(&format!(
+            "      <option value=\"{}\">{}</option>\n",
+            position + 1,
+            option
+        ));
+    }
+    select.push_str("    </select>\n    ");
+    select
+}
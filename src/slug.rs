@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+
+// Slugs that were not used yet in one address space (the products, or the
+// children of a category). Repeated slugs get a numeric suffix, and texts
+// without any letter or number use the fallback.
+#[derive(Debug, Default)]
+pub struct UniqueSlugs {
+    used: HashSet<String>,
+}
+
+impl UniqueSlugs {
+    pub fn reserve(&mut self, slug: &str) {
+        self.used.insert(slug.to_owned());
+    }
+
+    pub fn slugify(&mut self, text: &str, fallback: &str) -> String {
+        let slug = match slugify(text) {
+            slug if slug.is_empty() => slugify(fallback),
+            slug => slug,
+        };
+        let mut unique_slug = slug.clone();
+        let mut suffix = 1;
+        while self.used.contains(&unique_slug) {
+            suffix += 1;
+            unique_slug = format!("{}-{}", slug, suffix);
+        }
+        self.used.insert(unique_slug.clone());
+        unique_slug
+    }
+}
+
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        let c = match c {
+            'á' | 'à' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'í' | 'ì' | 'î' | 'ï' => 'i',
+            'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+            'ú' | 'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            'ñ' => 'n',
+            'ý' | 'ÿ' => 'y',
+            c => c,
+        };
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn slugifies_portuguese_text() {
+        assert_eq!("camisetas-regatas", slugify("Camisetas & Regatas"));
+        assert_eq!(
+            "acessorios-para-cabelo",
+            slugify(" Acessórios para Cabelo ")
+        );
+        assert_eq!("promocao-50", slugify("Promoção -50%!"));
+        assert_eq!("", slugify("!!!"));
+    }
+
+    #[test]
+    fn makes_slugs_unique() {
+        let mut slugs = UniqueSlugs::default();
+        slugs.reserve("cafe");
+        assert_eq!("cafe-2", slugs.slugify("Café", "1"));
+        assert_eq!("cafe-3", slugs.slugify("CAFE", "2"));
+        assert_eq!("produto-3", slugs.slugify("???", "Produto 3"));
+    }
+}
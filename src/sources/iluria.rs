@@ -0,0 +1,574 @@
+use super::ProductSource;
+use crate::money::{self, Locale, Money};
+use crate::run::{Availability, Installments, Product, Promotion, Variation};
+use crate::shipping::{self, Shipping};
+use crate::simulator;
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+
+// Iluria product pages are at /pd-<id>, with the id of the stock report.
+pub struct Iluria;
+
+impl ProductSource for Iluria {
+    fn product_url(&self, base_url: &str, product: &Product) -> Result<String, String> {
+        Ok(format!(
+            "{}/pd-{}",
+            base_url.trim_end_matches('/'),
+            product.id
+        ))
+    }
+
+    fn simulated_page(
+        &self,
+        product: &Product,
+        fixtures_dir: Option<&str>,
+        locale: Locale,
+    ) -> Result<String, String> {
+        simulator::get_product_page(product, fixtures_dir, locale)
+    }
+
+    fn enrich_product(
+        &self,
+        product: &mut Product,
+        page: &str,
+        locale: Locale,
+    ) -> Result<(), String> {
+        enrich_product(product, page, locale)
+    }
+}
+
+fn enrich_product(product: &mut Product, body: &str, locale: Locale) -> Result<(), String> {
+    let fragment = Html::parse_document(body);
+    if is_unavailable(&fragment)? {
+        product.availability = Availability::Unavailable;
+    }
+    product.description = get_description(&fragment, &product.id)?;
+    let (categories, category_urls) = get_categories(&fragment, &product.id, &product.url)?
+        .into_iter()
+        .unzip();
+    product.categories = categories;
+    product.category_urls = category_urls;
+    product.pictures = get_pictures(&fragment, &product.id)?;
+    product.seo = super::get_seo(&fragment, &product.url)?;
+    // The structured data comes first, then the hidden inputs. Codes from the
+    // report are kept.
+    let codes = super::get_codes(&fragment)?;
+    for (value, code, input) in [
+        (&mut product.sku, codes.sku, "productSku"),
+        (&mut product.gtin, codes.gtin, "productEan"),
+        (&mut product.brand, codes.brand, "productBrand"),
+    ] {
+        super::fill_missing(value, code);
+        super::fill_missing(
+            value,
+            get_input_value(&fragment, input)?.unwrap_or_default(),
+        );
+    }
+    if let Some((price, promotion)) = get_promotion(&fragment, &product.id, locale)? {
+        product.price = price;
+        product.promotion = Some(promotion);
+    }
+    product
+        .shipping
+        .fill_from(&get_shipping(&fragment, &product.id, locale)?);
+    product.installments = get_text_from_selector(&fragment, ".product-installments")?
+        .and_then(|text| parse_installments(&text, locale));
+    product.variations = get_variations(&fragment, &product.id, product.price, locale)?
+        .into_iter()
+        .map(|v| Variation {
+            type1: v.type1,
+            type2: v.type2,
+            type3: v.type3,
+            name1: v.name1,
+            name2: v.name2,
+            name3: v.name3,
+            picture: v.picture,
+            price: v.price,
+            promotional_price: v.promotional_price,
+            sku: v.sku,
+            gtin: v.gtin,
+        })
+        .collect();
+    Ok(())
+}
+
+fn get_variations(
+    fragment: &Html,
+    product_id: &str,
+    product_price: Money,
+    locale: Locale,
+) -> Result<Vec<VariationWithId>, String> {
+    let variations_selector = Selector::parse("input.allVariations").map_err(|e| {
+        format!(
+            "Could not get variations for product {}: {:?}",
+            product_id, e
+        )
+    })?;
+    let variations_select = fragment.select(&variations_selector);
+    let mut variations = variations_select
+        .map(|e| e.value())
+        .map(|e| {
+            printlnv!("Found variation input: {:?}", e);
+            let price = |attribute: &str| {
+                e.attr(attribute)
+                    .map(|price| {
+                        money::parse(price, locale).map_err(|err| {
+                            format!(
+                                "Could not get variation price for product {}: {}",
+                                product_id, err
+                            )
+                        })
+                    })
+                    .transpose()
+            };
+            let converted_price = price("convertedprice")?.unwrap_or(product_price);
+            // Variations on sale have the regular price too.
+            let (price, promotional_price) = match price("convertedoriginalprice")? {
+                Some(original) if original > converted_price => (original, Some(converted_price)),
+                _ => (converted_price, None),
+            };
+            Ok(VariationWithId {
+                type1: "".to_owned(),
+                type2: None,
+                type3: None,
+                name1: "".to_owned(),
+                name2: None,
+                name3: None,
+                id1: if let Some(v) = e.attr("value1") {
+                    v.trim()
+                } else {
+                    ""
+                }
+                .to_owned(),
+                id2: if let Some(v) = e.attr("value2") {
+                    let trimmed = v.trim();
+                    if trimmed.is_empty() {
+                        None
+                    } else {
+                        Some(trimmed.to_owned())
+                    }
+                } else {
+                    None
+                },
+                id3: if let Some(v) = e.attr("value3") {
+                    let trimmed = v.trim();
+                    if trimmed.is_empty() {
+                        None
+                    } else {
+                        Some(trimmed.to_owned())
+                    }
+                } else {
+                    None
+                },
+                price,
+                promotional_price,
+                sku: e.attr("sku").unwrap_or_default().trim().to_owned(),
+                gtin: e.attr("ean").unwrap_or_default().trim().to_owned(),
+                picture: e.attr("mainpictureurl550").map(get_picture_url),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut variation_ids_1 = HashMap::new();
+    for id in variations
+        .iter()
+        .map(|v| &v.id1)
+        .filter(|id| !id.is_empty())
+    {
+        variation_ids_1.insert(id.clone(), "".to_owned());
+    }
+    let mut variation_ids_2 = HashMap::new();
+    let mut variation_ids_3 = HashMap::new();
+    for id in variations.iter().filter_map(|v| v.id2.as_ref()) {
+        variation_ids_2.insert(id.clone(), "".to_owned());
+    }
+    for id in variations.iter().filter_map(|v| v.id3.as_ref()) {
+        variation_ids_3.insert(id.clone(), "".to_owned());
+    }
+
+    let variation_type_1 =
+        get_text_from_selector(fragment, "#iluria-product-variation1 > option[value='0']")?;
+    let variation_type_2 =
+        get_text_from_selector(fragment, "#iluria-product-variation2 > option[value='0']")?;
+    let variation_type_3 =
+        get_text_from_selector(fragment, "#iluria-product-variation3 > option[value='0']")?;
+
+    for (id, value) in variation_ids_1.iter_mut() {
+        *value = if let Some(value) = get_text_from_selector(
+            fragment,
+            &format!("#iluria-product-variation1 > option[value='{}']", id),
+        )? {
+            value
+        } else {
+            return Err(format!("Could not find variation value for id '{}'.", id));
+        }
+    }
+    for (id, value) in variation_ids_2.iter_mut() {
+        *value = if let Some(value) = get_text_from_selector(
+            fragment,
+            &format!("#iluria-product-variation2 > option[value='{}']", id),
+        )? {
+            value
+        } else {
+            return Err(format!("Could not find variation value for id '{}'.", id));
+        }
+    }
+    for (id, value) in variation_ids_3.iter_mut() {
+        *value = if let Some(value) = get_text_from_selector(
+            fragment,
+            &format!("#iluria-product-variation3 > option[value='{}']", id),
+        )? {
+            value
+        } else {
+            return Err(format!("Could not find variation value for id '{}'.", id));
+        }
+    }
+    for variation in variations.iter_mut() {
+        variation.type1 = variation_type_1.clone().unwrap();
+        variation.type2 = variation_type_2.clone();
+        variation.type3 = variation_type_3.clone();
+        variation.name1 = variation_ids_1[&variation.id1].clone();
+        if let Some(v) = &variation.id2 {
+            variation.name2 = Some(variation_ids_2[v].clone());
+        }
+        if let Some(v) = &variation.id3 {
+            variation.name3 = Some(variation_ids_3[v].clone());
+        }
+    }
+    Ok(variations)
+}
+
+// Products taken off sale still have a page, which says so in its own
+// element. The same text elsewhere, like in the description, is not checked.
+fn is_unavailable(fragment: &Html) -> Result<bool, String> {
+    Ok(get_text_from_selector(fragment, ".unavailable-product")?.is_some())
+}
+
+// Products on sale show the regular price ("de") struck through next to the
+// price they are sold for ("por"), and may show how long the sale lasts.
+fn get_promotion(
+    fragment: &Html,
+    product_id: &str,
+    locale: Locale,
+) -> Result<Option<(Money, Promotion)>, String> {
+    let price = |selector: &str| -> Result<Option<Money>, String> {
+        get_text_from_selector(fragment, selector)?
+            .filter(|text| !text.is_empty())
+            .map(|text| {
+                money::parse(&text, locale)
+                    .map_err(|e| format!("Could not get price for product {}: {}", product_id, e))
+            })
+            .transpose()
+    };
+    let (regular_price, sale_price) =
+        match (price(".product-price-original")?, price(".product-price")?) {
+            (Some(regular_price), Some(sale_price)) if regular_price > sale_price => {
+                (regular_price, sale_price)
+            }
+            _ => return Ok(None),
+        };
+    let dates = get_text_from_selector(fragment, ".product-promotion-period")?
+        .map(|text| find_dates(&text))
+        .unwrap_or_default();
+    let (start, end) = match dates.as_slice() {
+        [start, end, ..] => (Some(start.clone()), Some(end.clone())),
+        [end] => (None, Some(end.clone())),
+        [] => (None, None),
+    };
+    Ok(Some((
+        regular_price,
+        Promotion {
+            price: sale_price,
+            start,
+            end,
+        },
+    )))
+}
+
+// The shipping calculator of the page has the weight and dimensions in hidden
+// inputs. Values from the report are kept.
+fn get_shipping(fragment: &Html, product_id: &str, locale: Locale) -> Result<Shipping, String> {
+    let measure = |id: &str| -> Result<Option<u32>, String> {
+        match get_input_value(fragment, id)? {
+            Some(value) => shipping::parse_measure(&value, locale)
+                .map_err(|e| format!("Could not get {} of product {}: {}", id, product_id, e)),
+            None => Ok(None),
+        }
+    };
+    Ok(Shipping {
+        weight: measure("productWeight")?,
+        height: measure("productHeight")?,
+        width: measure("productWidth")?,
+        length: measure("productLength")?,
+    })
+}
+
+fn get_input_value(fragment: &Html, id: &str) -> Result<Option<String>, String> {
+    let selector = Selector::parse(&format!("input#{}", id))
+        .map_err(|e| format!("Could not get value of input {}: {:?}", id, e))?;
+    Ok(fragment
+        .select(&selector)
+        .next()
+        .and_then(|input| input.value().attr("value"))
+        .map(|value| value.trim().to_owned()))
+}
+
+fn find_dates(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_ascii_digit() && c != '/')
+        .filter(|word| {
+            let parts = word.split('/').map(str::len).collect::<Vec<_>>();
+            parts == [2, 2, 4] && word.chars().all(|c| c.is_ascii_digit() || c == '/')
+        })
+        .map(|date| date.to_owned())
+        .collect()
+}
+
+// As in "em até 3x de R$ 16,63 sem juros".
+fn parse_installments(text: &str, locale: Locale) -> Option<Installments> {
+    let (count, rest) = text.split_once("x de ")?;
+    let count = count.split_whitespace().last()?.parse().ok()?;
+    let lowercase = rest.to_lowercase();
+    let value = match lowercase
+        .find(" sem juros")
+        .or_else(|| lowercase.find(" com juros"))
+    {
+        Some(position) => &rest[..position],
+        None => rest,
+    };
+    Some(Installments {
+        count,
+        value: money::parse(value, locale).ok()?,
+        interest_free: lowercase.contains("sem juros"),
+    })
+}
+
+fn get_text_from_selector(fragment: &Html, selector: &str) -> Result<Option<String>, String> {
+    let variation1_selector = Selector::parse(selector)
+        .map_err(|e| format!("Could not get value for selector {}: {:?}", selector, e))?;
+    let mut select = fragment.select(&variation1_selector);
+    Ok(select
+        .next()
+        .map(|d| d.text().collect::<String>().trim().to_owned()))
+}
+
+fn get_description(fragment: &Html, product_id: &str) -> Result<String, String> {
+    let description_selector =
+        Selector::parse("div:not([id]).product-description").map_err(|e| {
+            format!(
+                "Could not get description for product {}: {:?}",
+                product_id, e
+            )
+        })?;
+    Ok(
+        if let Some(d) = fragment.select(&description_selector).next() {
+            d.inner_html()
+        } else {
+            "".to_owned()
+        },
+    )
+}
+
+fn get_categories(
+    fragment: &Html,
+    product_id: &str,
+    product_url: &str,
+) -> Result<Vec<(String, String)>, String> {
+    let category_selector = Selector::parse(".breadcrumb a")
+        .map_err(|e| format!("Could not get category for product {}: {:?}", product_id, e))?;
+    let product_url = url::Url::parse(product_url)
+        .map_err(|e| format!("Invalid url for product {}: {}", product_id, e))?;
+    let categories = fragment
+        .select(&category_selector)
+        .skip(2)
+        .map(|a| {
+            let url = a
+                .value()
+                .attr("href")
+                .and_then(|href| product_url.join(href).ok())
+                .map(|url| url.to_string())
+                .unwrap_or_default();
+            (a.text().collect::<String>().trim().to_owned(), url)
+        })
+        .filter(|(category, _)| !category.is_empty())
+        .collect();
+    Ok(categories)
+}
+
+fn get_pictures(fragment: &Html, product_id: &str) -> Result<Vec<String>, String> {
+    let images_selector = Selector::parse("#thumbsContainer img")
+        .map_err(|e| format!("Could not get images for product {}: {:?}", product_id, e))?;
+    let pictures = fragment
+        .select(&images_selector)
+        .filter_map(|i| i.value().attr("mainpictureurl"))
+        .map(get_picture_url)
+        .collect();
+    Ok(pictures)
+}
+
+fn get_picture_url(url: &str) -> String {
+    if url.starts_with('/') {
+        format!("http:{}", url)
+    } else {
+        url.to_owned()
+    }
+}
+
+struct VariationWithId {
+    id1: String,
+    id2: Option<String>,
+    id3: Option<String>,
+    type1: String,
+    type2: Option<String>,
+    type3: Option<String>,
+    name1: String,
+    name2: Option<String>,
+    name3: Option<String>,
+    price: Money,
+    promotional_price: Option<Money>,
+    picture: Option<String>,
+    sku: String,
+    gtin: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn simulated_product(id: &str) -> Result<Product, String> {
+        let mut product = Product {
+            id: id.to_owned(),
+            url: format!("http://loja.com.br/pd-{}", id),
+            name: "Camiseta".to_owned(),
+            price: Money::from_cents(5000),
+            ..Default::default()
+        };
+        let page = simulator::get_product_page(&product, None, Locale::PtBr)?;
+        Iluria.enrich_product(&mut product, &page, Locale::PtBr)?;
+        Ok(product)
+    }
+
+    #[test]
+    fn finds_unavailable_products_by_their_element() -> Result<(), String> {
+        let page =
+            |body: &str| Html::parse_document(&format!("<html><body>{}</body></html>", body));
+        assert!(is_unavailable(&page(
+            "<div class=\"unavailable-product\">Produto Indisponível</div>"
+        ))?);
+        assert!(!is_unavailable(&page(
+            "<script>var text = 'Produto indisponível';</script>\
+             <div class=\"product-description\">Produto indisponível na cor azul.</div>"
+        ))?);
+        Ok(())
+    }
+
+    #[test]
+    fn parses_simulated_pages() -> Result<(), String> {
+        // The ids are chosen for each kind of generated page.
+        let product = simulated_product("5")?;
+        assert_eq!(vec!["Simulação", "Categoria 3"], product.categories);
+        assert_eq!(
+            "http://loja.com.br/categoria-3-c30",
+            product.category_urls[1]
+        );
+        assert_eq!(2, product.pictures.len());
+        assert_eq!(
+            "http://img.simulacao.iluria.com/5/1.jpg",
+            product.pictures[0]
+        );
+        assert!(product.description.contains("Camiseta, produto simulado"));
+        assert_eq!("Camiseta", product.seo.title);
+        assert_eq!("Camiseta, produto simulado.", product.seo.description);
+        assert_eq!("http://loja.com.br/pd-5", product.seo.canonical_url);
+        let variations = product
+            .variations
+            .iter()
+            .map(|v| {
+                (
+                    v.type1.as_str(),
+                    v.name1.as_str(),
+                    v.type2.as_deref(),
+                    v.name2.as_deref(),
+                    v.price,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(6, variations.len());
+        assert_eq!(
+            Some(Money::from_cents(4000)),
+            product.promotion.as_ref().map(|p| p.price)
+        );
+        assert_eq!(
+            Some(Money::from_cents(4000)),
+            product.variations[0].promotional_price
+        );
+        assert_eq!(Some(2), product.installments.as_ref().map(|i| i.count));
+        assert_eq!(
+            Shipping {
+                weight: Some(300),
+                height: Some(6),
+                width: Some(15),
+                length: Some(20),
+            },
+            product.shipping
+        );
+        assert_eq!(
+            (
+                "Tamanho",
+                "P",
+                Some("Cor"),
+                Some("Azul"),
+                Money::from_cents(5000)
+            ),
+            variations[0]
+        );
+        assert_eq!(
+            (
+                "Tamanho",
+                "G",
+                Some("Cor"),
+                Some("Preto"),
+                Money::from_cents(5500)
+            ),
+            variations[5]
+        );
+        assert_eq!(None, simulated_product("3")?.promotion);
+        assert_eq!(3, simulated_product("1")?.variations.len());
+        assert_eq!(0, simulated_product("3")?.variations.len());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_promotions_and_installments() -> Result<(), String> {
+        let page = Html::parse_document(
+            "<span class=\"product-price-original\">R$ 59,90</span>\
+             <span class=\"product-price\">R$ 49,90</span>\
+             <span class=\"product-promotion-period\">De 01/12/2021 até 31/12/2021.</span>",
+        );
+        assert_eq!(
+            Some((
+                Money::from_cents(5990),
+                Promotion {
+                    price: Money::from_cents(4990),
+                    start: Some("01/12/2021".to_owned()),
+                    end: Some("31/12/2021".to_owned()),
+                }
+            )),
+            get_promotion(&page, "1", Locale::PtBr)?
+        );
+        assert_eq!(
+            Some(Installments {
+                count: 3,
+                value: Money::from_cents(1663),
+                interest_free: true,
+            }),
+            parse_installments("em até 3x de R$ 16,63 sem juros", Locale::PtBr)
+        );
+        assert_eq!(
+            Some(false),
+            parse_installments("10x de R$ 5,00 com juros", Locale::PtBr).map(|i| i.interest_free)
+        );
+        assert_eq!(None, parse_installments("à vista", Locale::PtBr));
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+mod iluria;
+mod nuvemshop;
+
+use crate::money::Locale;
+use crate::run::{Product, Seo};
+use scraper::{Html, Selector};
+use std::{fmt, str::FromStr};
+
+// A storefront the products are read from. Each source knows where the page
+// of a product is and how to read it into the product model.
+pub trait ProductSource {
+    fn product_url(&self, base_url: &str, product: &Product) -> Result<String, String>;
+    fn simulated_page(
+        &self,
+        product: &Product,
+        fixtures_dir: Option<&str>,
+        locale: Locale,
+    ) -> Result<String, String>;
+    fn enrich_product(
+        &self,
+        product: &mut Product,
+        page: &str,
+        locale: Locale,
+    ) -> Result<(), String>;
+}
+
+// Every storefront has the SEO data in the head of the page. The canonical url
+// may be relative to the product page.
+fn get_seo(fragment: &Html, product_url: &str) -> Result<Seo, String> {
+    let first = |selector: &str, attribute: Option<&str>| -> Result<String, String> {
+        let selector = Selector::parse(selector)
+            .map_err(|e| format!("Could not get value for selector {}: {:?}", selector, e))?;
+        Ok(fragment
+            .select(&selector)
+            .next()
+            .map(|e| match attribute {
+                Some(attribute) => e.value().attr(attribute).unwrap_or_default().to_owned(),
+                None => e.text().collect(),
+            })
+            .unwrap_or_default()
+            .trim()
+            .to_owned())
+    };
+    let canonical_url = first("link[rel='canonical']", Some("href"))?;
+    Ok(Seo {
+        title: first("head title", None)?,
+        description: first("meta[name='description']", Some("content"))?,
+        keywords: first("meta[name='keywords']", Some("content"))?,
+        canonical_url: match url::Url::parse(product_url).and_then(|url| url.join(&canonical_url)) {
+            Ok(url) if !canonical_url.is_empty() => url.to_string(),
+            _ => canonical_url,
+        },
+    })
+}
+
+#[derive(Debug, Default)]
+struct Codes {
+    sku: String,
+    gtin: String,
+    brand: String,
+}
+
+// Codes from the structured data of the page (JSON-LD), where the barcode may
+// be in any of the gtin properties and the brand may be a name or a Brand.
+fn get_codes(fragment: &Html) -> Result<Codes, String> {
+    let selector = Selector::parse("script[type='application/ld+json']")
+        .map_err(|e| format!("Could not get structured data: {:?}", e))?;
+    let data = fragment
+        .select(&selector)
+        .filter_map(|script| {
+            serde_json::from_str::<serde_json::Value>(&script.text().collect::<String>()).ok()
+        })
+        .find(|data| data.get("@type").and_then(|kind| kind.as_str()) == Some("Product"));
+    let data = match data {
+        Some(data) => data,
+        None => return Ok(Codes::default()),
+    };
+    let text = |value: Option<&serde_json::Value>| match value {
+        Some(serde_json::Value::String(text)) => text.trim().to_owned(),
+        Some(serde_json::Value::Number(number)) => number.to_string(),
+        _ => "".to_owned(),
+    };
+    Ok(Codes {
+        sku: text(data.get("sku")),
+        gtin: ["gtin13", "gtin", "gtin14", "gtin12", "gtin8"]
+            .iter()
+            .map(|property| text(data.get(property)))
+            .find(|gtin| !gtin.is_empty())
+            .unwrap_or_default(),
+        brand: match data.get("brand") {
+            Some(brand @ serde_json::Value::Object(_)) => text(brand.get("name")),
+            brand => text(brand),
+        },
+    })
+}
+
+fn fill_missing(value: &mut String, other: String) {
+    if value.is_empty() {
+        *value = other;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Source {
+    #[default]
+    Iluria,
+    Nuvemshop,
+}
+
+impl Source {
+    pub fn product_source(self) -> Box<dyn ProductSource> {
+        match self {
+            Source::Iluria => Box::new(iluria::Iluria),
+            Source::Nuvemshop => Box::new(nuvemshop::Nuvemshop),
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "iluria" => Ok(Source::Iluria),
+            "nuvemshop" | "tiendanube" => Ok(Source::Nuvemshop),
+            _ => Err(format!(
+                "Unknown source '{}', use one of: iluria, nuvemshop.",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Iluria => write!(f, "iluria"),
+            Source::Nuvemshop => write!(f, "nuvemshop"),
+        }
+    }
+}
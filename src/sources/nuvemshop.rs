@@ -0,0 +1,420 @@
+use super::ProductSource;
+use crate::money::{self, Locale, Money};
+use crate::run::{Availability, Product, Promotion, Variation};
+use crate::simulator;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+
+// Nuvemshop (Tiendanube) product pages are at /produtos/<handle>, so the report
+// has to have the handle of each product, in the Handle column or else in the
+// product column, which is also the id of the product in the export. The
+// product data comes from the structured data of the page and the variants
+// from the product form, which every theme has.
+pub struct Nuvemshop;
+
+impl ProductSource for Nuvemshop {
+    fn product_url(&self, base_url: &str, product: &Product) -> Result<String, String> {
+        let handle = if product.handle.is_empty() {
+            &product.id
+        } else {
+            &product.handle
+        };
+        if !is_handle(handle) {
+            return Err(format!(
+                "Product {} has no Nuvemshop handle, '{}' is not one. Add a Handle column to the report with the end of the url of each product, like camiseta-basica.",
+                product.id, handle
+            ));
+        }
+        Ok(format!(
+            "{}/produtos/{}/",
+            base_url.trim_end_matches('/'),
+            handle
+        ))
+    }
+
+    fn simulated_page(
+        &self,
+        product: &Product,
+        fixtures_dir: Option<&str>,
+        _locale: Locale,
+    ) -> Result<String, String> {
+        simulator::get_recorded_page(product, fixtures_dir)?.ok_or_else(|| {
+            format!(
+                "Simulating Nuvemshop needs a recorded page for product {}, use --fixtures.",
+                product.id
+            )
+        })
+    }
+
+    fn enrich_product(
+        &self,
+        product: &mut Product,
+        page: &str,
+        locale: Locale,
+    ) -> Result<(), String> {
+        let fragment = Html::parse_document(page);
+        let data = get_structured_data(&fragment, &product.id)?;
+        if data.is_unavailable() {
+            product.availability = Availability::Unavailable;
+        }
+        product.description = match select_first(&fragment, "[data-store^='product-description']")?
+        {
+            Some(description) => description.inner_html().trim().to_owned(),
+            None => data.description.unwrap_or_default(),
+        };
+        let (categories, category_urls) =
+            get_categories(&fragment, &product.url)?.into_iter().unzip();
+        product.categories = categories;
+        product.category_urls = category_urls;
+        product.pictures = match data.image {
+            Some(Images::One(image)) => vec![get_picture_url(&image)],
+            Some(Images::Many(images)) => images.iter().map(|i| get_picture_url(i)).collect(),
+            None => vec![],
+        };
+        let variants = get_variants(&fragment, &product.id)?;
+        // Products without options have a single variant with the prices.
+        if let Some(variant) = variants.first() {
+            let (price, promotional_price) = variant.prices(product, locale)?;
+            if let Some(promotional_price) = promotional_price {
+                product.price = price;
+                product.promotion = Some(Promotion {
+                    price: promotional_price,
+                    start: None,
+                    end: None,
+                });
+            }
+        }
+        product.seo = super::get_seo(&fragment, &product.url)?;
+        let codes = super::get_codes(&fragment)?;
+        super::fill_missing(&mut product.sku, codes.sku);
+        super::fill_missing(&mut product.gtin, codes.gtin);
+        super::fill_missing(&mut product.brand, codes.brand);
+        product.variations = get_variations(&fragment, product, variants, locale)?;
+        Ok(())
+    }
+}
+
+// Handles are slugs, made of lowercase letters, digits and hyphens.
+fn is_handle(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+#[derive(Debug, Deserialize)]
+struct StructuredData {
+    #[serde(rename = "@type")]
+    kind: Option<String>,
+    description: Option<String>,
+    image: Option<Images>,
+    offers: Option<serde_json::Value>,
+}
+
+impl StructuredData {
+    // Offers can be a single offer or a list of them, one for each variant, and
+    // the product is only unavailable when none of them is available.
+    fn is_unavailable(&self) -> bool {
+        let offers = match &self.offers {
+            Some(serde_json::Value::Array(offers)) => offers.iter().collect(),
+            Some(offer) => vec![offer],
+            None => vec![],
+        };
+        let availabilities = offers
+            .iter()
+            .filter_map(|offer| offer.get("availability").and_then(|a| a.as_str()))
+            .collect::<Vec<_>>();
+        !availabilities.is_empty()
+            && availabilities.iter().all(|availability| {
+                availability.ends_with("OutOfStock") || availability.ends_with("Discontinued")
+            })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Images {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Variant {
+    option0: Option<String>,
+    option1: Option<String>,
+    option2: Option<String>,
+    price_short: Option<String>,
+    compare_at_price_short: Option<String>,
+    image_url: Option<String>,
+    sku: Option<String>,
+    barcode: Option<String>,
+}
+
+impl Variant {
+    // Variants on sale have the regular price as the compare at price.
+    fn prices(&self, product: &Product, locale: Locale) -> Result<(Money, Option<Money>), String> {
+        let parse = |price: &Option<String>| {
+            price
+                .as_deref()
+                .filter(|price| !price.is_empty())
+                .map(|price| {
+                    money::parse(price, locale).map_err(|e| {
+                        format!(
+                            "Could not get variation price for product {}: {}",
+                            product.id, e
+                        )
+                    })
+                })
+                .transpose()
+        };
+        let price = parse(&self.price_short)?.unwrap_or(product.price);
+        Ok(match parse(&self.compare_at_price_short)? {
+            Some(regular_price) if regular_price > price => (regular_price, Some(price)),
+            _ => (price, None),
+        })
+    }
+}
+
+fn select_first<'a>(
+    fragment: &'a Html,
+    selector: &str,
+) -> Result<Option<scraper::ElementRef<'a>>, String> {
+    let selector = Selector::parse(selector)
+        .map_err(|e| format!("Could not get value for selector {}: {:?}", selector, e))?;
+    Ok(fragment.select(&selector).next())
+}
+
+fn get_structured_data(fragment: &Html, product_id: &str) -> Result<StructuredData, String> {
+    let selector = Selector::parse("script[type='application/ld+json']")
+        .map_err(|e| format!("Could not get data for product {}: {:?}", product_id, e))?;
+    fragment
+        .select(&selector)
+        .filter_map(|script| {
+            serde_json::from_str::<StructuredData>(&script.text().collect::<String>()).ok()
+        })
+        .find(|data| data.kind.as_deref() == Some("Product"))
+        .ok_or_else(|| format!("Could not find the data of product {}.", product_id))
+}
+
+fn get_categories(fragment: &Html, product_url: &str) -> Result<Vec<(String, String)>, String> {
+    let selector = Selector::parse(".breadcrumbs a.crumb")
+        .map_err(|e| format!("Could not get categories: {:?}", e))?;
+    let product_url = url::Url::parse(product_url)
+        .map_err(|e| format!("Invalid url '{}': {}", product_url, e))?;
+    Ok(fragment
+        .select(&selector)
+        .skip(1)
+        .map(|a| {
+            let url = a
+                .value()
+                .attr("href")
+                .and_then(|href| product_url.join(href).ok())
+                .map(|url| url.to_string())
+                .unwrap_or_default();
+            (a.text().collect::<String>().trim().to_owned(), url)
+        })
+        .filter(|(category, _)| !category.is_empty())
+        .collect())
+}
+
+fn get_variants(fragment: &Html, product_id: &str) -> Result<Vec<Variant>, String> {
+    match select_first(fragment, "#product_form")?
+        .and_then(|form| form.value().attr("data-variants"))
+    {
+        Some(variants) => serde_json::from_str::<Vec<Variant>>(variants)
+            .map_err(|e| format!("Could not get variations for product {}: {}", product_id, e)),
+        None => Ok(vec![]),
+    }
+}
+
+fn get_variations(
+    fragment: &Html,
+    product: &Product,
+    variants: Vec<Variant>,
+    locale: Locale,
+) -> Result<Vec<Variation>, String> {
+    let selector = Selector::parse(".js-product-variants-group label")
+        .map_err(|e| format!("Could not get variation types: {:?}", e))?;
+    let types = fragment
+        .select(&selector)
+        .map(|label| {
+            label
+                .text()
+                .collect::<String>()
+                .trim()
+                .trim_end_matches(':')
+                .to_owned()
+        })
+        .collect::<Vec<_>>();
+    variants
+        .into_iter()
+        .filter(|variant| variant.option0.is_some())
+        .map(|variant| {
+            let (price, promotional_price) = variant.prices(product, locale)?;
+            Ok(Variation {
+                type1: types.first().cloned().unwrap_or_default(),
+                type2: variant.option1.as_ref().and(types.get(1).cloned()),
+                type3: variant.option2.as_ref().and(types.get(2).cloned()),
+                name1: variant.option0.unwrap_or_default(),
+                name2: variant.option1,
+                name3: variant.option2,
+                price,
+                promotional_price,
+                sku: variant.sku.unwrap_or_default(),
+                gtin: variant.barcode.unwrap_or_default(),
+                picture: variant.image_url.as_deref().map(get_picture_url),
+            })
+        })
+        .collect()
+}
+
+fn get_picture_url(url: &str) -> String {
+    if url.starts_with("//") {
+        format!("https:{}", url)
+    } else {
+        url.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use crate::run::Seo;
+    use pretty_assertions::assert_eq;
+
+    const PAGE: &str = r#"<html><head>
+        <title>Camiseta - Loja</title>
+        <meta name="keywords" content="camiseta, algodão">
+        <link rel="canonical" href="https://loja.com.br/produtos/camiseta/">
+        <script type="application/ld+json">{"@context": "https://schema.org", "@type": "BreadcrumbList"}</script>
+        <script type="application/ld+json">
+        {"@context": "https://schema.org/", "@type": "Product", "name": "Camiseta",
+         "description": "Camiseta de algodão", "image": ["//cdn.nuvemshop.com.br/1.jpg", "https://cdn.nuvemshop.com.br/2.jpg"],
+         "offers": {"@type": "Offer", "availability": "http://schema.org/InStock"}}
+        </script></head><body>
+        <div class="breadcrumbs">
+          <a class="crumb" href="/">Início</a>
+          <a class="crumb" href="/roupas/">Roupas</a>
+          <a class="crumb" href="/roupas/camisetas/">Camisetas</a>
+          <span class="crumb active">Camiseta</span>
+        </div>
+        <form id="product_form" data-variants='[{"option0":"P","option1":"Azul","option2":null,"price_short":"R$49,90","compare_at_price_short":"R$59,90","image_url":"//cdn.nuvemshop.com.br/p.jpg"},{"option0":"G","option1":"Azul","option2":null,"price_short":"R$54,90","compare_at_price_short":"","image_url":null}]'>
+          <div class="js-product-variants-group"><label class="form-label">Tamanho:</label></div>
+          <div class="js-product-variants-group"><label class="form-label">Cor:</label></div>
+        </form>
+        <div class="user-content" data-store="product-description-1"><p>Camiseta de <b>algodão</b></p></div>
+        </body></html>"#;
+
+    #[test]
+    fn reads_product_pages() -> Result<(), String> {
+        let mut product = Product {
+            id: "camiseta".to_owned(),
+            price: Money::from_cents(4990),
+            ..Default::default()
+        };
+        product.url = Nuvemshop.product_url("https://loja.com.br/", &product)?;
+        Nuvemshop.enrich_product(&mut product, PAGE, Locale::PtBr)?;
+        assert_eq!("https://loja.com.br/produtos/camiseta/", product.url);
+        assert!(product.is_active());
+        assert_eq!(Money::from_cents(5990), product.price);
+        assert_eq!(
+            Some(Money::from_cents(4990)),
+            product.promotion.as_ref().map(|p| p.price)
+        );
+        assert_eq!("<p>Camiseta de <b>algodão</b></p>", product.description);
+        assert_eq!(vec!["Roupas", "Camisetas"], product.categories);
+        assert_eq!(
+            Seo {
+                title: "Camiseta - Loja".to_owned(),
+                description: "".to_owned(),
+                keywords: "camiseta, algodão".to_owned(),
+                canonical_url: "https://loja.com.br/produtos/camiseta/".to_owned(),
+            },
+            product.seo
+        );
+        assert_eq!(
+            "https://loja.com.br/roupas/camisetas/",
+            product.category_urls[1]
+        );
+        assert_eq!(
+            vec![
+                "https://cdn.nuvemshop.com.br/1.jpg",
+                "https://cdn.nuvemshop.com.br/2.jpg"
+            ],
+            product.pictures
+        );
+        assert_eq!(
+            vec![
+                (
+                    "Tamanho",
+                    "P",
+                    Some("Cor"),
+                    Some("Azul"),
+                    Money::from_cents(5990),
+                    Some(Money::from_cents(4990)),
+                    Some("https://cdn.nuvemshop.com.br/p.jpg")
+                ),
+                (
+                    "Tamanho",
+                    "G",
+                    Some("Cor"),
+                    Some("Azul"),
+                    Money::from_cents(5490),
+                    None,
+                    None
+                ),
+            ],
+            product
+                .variations
+                .iter()
+                .map(|v| (
+                    v.type1.as_str(),
+                    v.name1.as_str(),
+                    v.type2.as_deref(),
+                    v.name2.as_deref(),
+                    v.price,
+                    v.promotional_price,
+                    v.picture.as_deref()
+                ))
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn finds_pages_by_the_handle() {
+        let product = |id: &str, handle: &str| Product {
+            id: id.to_owned(),
+            handle: handle.to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(
+            Ok("https://loja.com.br/produtos/camiseta-basica/".to_owned()),
+            Nuvemshop.product_url("https://loja.com.br", &product("10", "camiseta-basica"))
+        );
+        assert_eq!(
+            Ok("https://loja.com.br/produtos/caneca/".to_owned()),
+            Nuvemshop.product_url("https://loja.com.br", &product("caneca", ""))
+        );
+        assert!(Nuvemshop
+            .product_url("https://loja.com.br", &product("Caneca Azul", ""))
+            .is_err());
+    }
+
+    #[test]
+    fn is_unavailable_only_when_no_variant_is_available() {
+        let data = |offers: &str| StructuredData {
+            kind: Some("Product".to_owned()),
+            description: None,
+            image: None,
+            offers: serde_json::from_str(offers).ok(),
+        };
+        let out_of_stock = r#"{"availability": "http://schema.org/OutOfStock"}"#;
+        let in_stock = r#"{"availability": "http://schema.org/InStock"}"#;
+        assert!(data(out_of_stock).is_unavailable());
+        assert!(!data(&format!("[{}, {}]", out_of_stock, in_stock)).is_unavailable());
+        assert!(data(&format!("[{}, {}, {{}}]", out_of_stock, out_of_stock)).is_unavailable());
+        assert!(!data("[]").is_unavailable());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{
+    ids::{self, IdMap},
+    importer::ProductWithVariation,
+    money::{Locale, Money},
+    pricing::{self, PriceRule},
+    run::Product,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+
+// A row of the bulk stock update file. Rows of products without variations
+// have an empty variation id.
+#[derive(Debug, Serialize)]
+pub struct StockUpdate {
+    product_id: String,
+    variation_id: String,
+    stock: Option<u32>,
+    price: Money,
+}
+
+// Stock and prices come straight from the report, so the product pages are
+// never scraped. Price rules still apply, except their category conditions,
+// as categories are only known from the product pages.
+pub fn get_stock_updates<I>(
+    rows: I,
+    limit: usize,
+    id_map: &mut IdMap,
+    price_rules: Option<&[PriceRule]>,
+    locale: Locale,
+) -> Result<Vec<StockUpdate>, String>
+where
+    I: IntoIterator<Item = Result<ProductWithVariation, String>>,
+{
+    let mut rows_read = HashSet::new();
+    let mut product_ids = HashSet::new();
+    let mut products = vec![];
+    let mut variations = vec![];
+    for row in rows {
+        let row = row?;
+        if !product_ids.contains(&row.produto) {
+            if limit != 0 && product_ids.len() >= limit {
+                break;
+            }
+            product_ids.insert(row.produto.clone());
+        }
+        if !rows_read.insert((row.produto.clone(), row.variacao.clone())) {
+            continue;
+        }
+        products.push(Product {
+            id: row.produto,
+            name: row.nome,
+            stock: row.estoque,
+            price: row.preco,
+            price_cost: row.preco_de_custo,
+            vendor_name: row.nome_do_fornecedor,
+            ..Default::default()
+        });
+        variations.push(row.variacao);
+    }
+    if let Some(price_rules) = price_rules {
+        if pricing::uses_categories(price_rules) {
+            printlnpb!("Warning: price rules with a category are ignored when updating stock.");
+        }
+        pricing::apply_rules(price_rules, &mut products, locale);
+    }
+    id_map.assign_ids(&products);
+    Ok(products
+        .into_iter()
+        .zip(variations)
+        .map(|(product, variation)| {
+            let product_id = id_map
+                .export_id(&product.id)
+                .expect("All products should have an export id.")
+                .to_owned();
+            StockUpdate {
+                variation_id: ids::variation_id(&product_id, &[&variation]),
+                product_id,
+                stock: product.stock,
+                price: product.price,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::Variation;
+    use pretty_assertions::assert_eq;
+
+    fn row(id: &str, variation: &str, stock: u32) -> Result<ProductWithVariation, String> {
+        Ok(ProductWithVariation {
+            produto: id.to_owned(),
+            nome: format!("Product {}", id),
+            variacao: variation.to_owned(),
+            estoque: Some(stock),
+            preco: Money::from_cents(1000),
+            preco_de_custo: None,
+            frete: Default::default(),
+            sku: "".to_owned(),
+            gtin: "".to_owned(),
+            marca: "".to_owned(),
+            handle: "".to_owned(),
+            nome_do_fornecedor: "".to_owned(),
+        })
+    }
+
+    #[test]
+    fn updates_stock_of_each_variation_with_stable_ids() -> Result<(), String> {
+        let rows = vec![
+            row("1", "Azul / P", 2),
+            row("1", "Azul / G", 0),
+            row("1", "Azul / P", 2),
+            row("2", "", 5),
+            row("3", "", 1),
+        ];
+        let updates = get_stock_updates(rows, 2, &mut IdMap::default(), None, Locale::PtBr)?
+            .into_iter()
+            .map(|u| (u.product_id, u.variation_id, u.stock))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("1".to_owned(), "1-azul-p".to_owned(), Some(2)),
+                ("1".to_owned(), "1-azul-g".to_owned(), Some(0)),
+                ("2".to_owned(), "".to_owned(), Some(5)),
+            ],
+            updates
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn gives_variations_the_ids_of_the_exported_ones() -> Result<(), String> {
+        let updates = get_stock_updates(
+            vec![row("1", "Azul / P", 2)],
+            0,
+            &mut IdMap::default(),
+            None,
+            Locale::PtBr,
+        )?;
+        let variation = Variation {
+            name1: "P".to_owned(),
+            name2: Some("Azul".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids::page_variation_id("1", &variation),
+            updates[0].variation_id
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+use crate::categories::PATH_SEPARATOR;
+use crate::run::{Product, Variation};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+// Products that changed since the previous run. Variation changes of products
+// that exist in both runs are kept apart, each in a copy of its product that
+// has only those variations.
+#[derive(Debug, Default)]
+pub struct CatalogChanges {
+    pub created: Vec<Product>,
+    pub updated: Vec<Product>,
+    pub deleted: Vec<Product>,
+    pub variations_created: Vec<Product>,
+    pub variations_updated: Vec<Product>,
+    pub variations_deleted: Vec<Product>,
+    descriptions: Vec<String>,
+}
+
+pub fn load_catalog(file: &str) -> Result<Vec<Product>, String> {
+    let contents = fs::read_to_string(file)
+        .map_err(|e| format!("Error when opening previous catalog file '{}': {}", file, e))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format!("Could not read previous catalog file '{}': {}", file, e))
+}
+
+pub fn catalog_to_json(products: &[Product]) -> Result<String, String> {
+    serde_json::to_string_pretty(products).map_err(|e| format!("Could not save catalog: {}", e))
+}
+
+pub fn diff(previous: Vec<Product>, current: &[Product]) -> CatalogChanges {
+    let mut changes = CatalogChanges::default();
+    let current_ids: HashSet<&str> = current.iter().map(|p| p.id.as_str()).collect();
+    let mut previous_products: HashMap<String, Product> = HashMap::new();
+    for product in previous {
+        if current_ids.contains(product.id.as_str()) {
+            previous_products.insert(product.id.clone(), product);
+        } else {
+            changes.descriptions.push(format!(
+                "Removed product {} '{}'.",
+                product.id, product.name
+            ));
+            changes.deleted.push(product);
+        }
+    }
+    for product in current {
+        let previous_product = match previous_products.remove(&product.id) {
+            Some(previous_product) => previous_product,
+            None => {
+                changes
+                    .descriptions
+                    .push(format!("New product {} '{}'.", product.id, product.name));
+                changes.created.push(product.clone());
+                continue;
+            }
+        };
+        let product_changes = describe_product_changes(&previous_product, product);
+        if !product_changes.is_empty() {
+            changes.descriptions.push(format!(
+                "Changed product {} '{}': {}.",
+                product.id,
+                product.name,
+                product_changes.join(", ")
+            ));
+            changes.updated.push(product.clone());
+        }
+        diff_variations(previous_product, product, &mut changes);
+    }
+    changes
+}
+
+fn diff_variations(previous_product: Product, product: &Product, changes: &mut CatalogChanges) {
+    let mut previous_variations: HashMap<_, Variation> = previous_product
+        .variations
+        .into_iter()
+        .map(|v| (variation_key(&v), v))
+        .collect();
+    let (mut created, mut updated) = (vec![], vec![]);
+    for variation in product.variations.iter() {
+        let name = variation_name(variation);
+        match previous_variations.remove(&variation_key(variation)) {
+            None => {
+                changes.descriptions.push(format!(
+                    "New variation '{}' of product {}.",
+                    name, product.id
+                ));
+                created.push(variation.clone());
+            }
+            Some(previous_variation) if previous_variation != *variation => {
+                changes.descriptions.push(format!(
+                    "Changed variation '{}' of product {}: {}.",
+                    name,
+                    product.id,
+                    describe_variation_changes(&previous_variation, variation).join(", ")
+                ));
+                updated.push(variation.clone());
+            }
+            Some(_) => {}
+        }
+    }
+    let mut deleted = previous_variations.into_values().collect::<Vec<_>>();
+    deleted.sort_by_key(variation_name);
+    for variation in deleted.iter() {
+        changes.descriptions.push(format!(
+            "Removed variation '{}' of product {}.",
+            variation_name(variation),
+            product.id
+        ));
+    }
+    for (variations, list) in [
+        (created, &mut changes.variations_created),
+        (updated, &mut changes.variations_updated),
+        (deleted, &mut changes.variations_deleted),
+    ] {
+        if !variations.is_empty() {
+            list.push(Product {
+                variations,
+                ..product_without_variations(product)
+            });
+        }
+    }
+}
+
+impl CatalogChanges {
+    pub fn summary(&self) -> String {
+        let count =
+            |products: &[Product]| -> usize { products.iter().map(|p| p.variations.len()).sum() };
+        let mut summary = format!(
+            "Products: {} new, {} changed, {} removed.\n\
+             Variations: {} new, {} changed, {} removed.\n",
+            self.created.len(),
+            self.updated.len(),
+            self.deleted.len(),
+            count(&self.variations_created) + count(&self.created),
+            count(&self.variations_updated),
+            count(&self.variations_deleted),
+        );
+        for description in self.descriptions.iter() {
+            summary.push_str(description);
+            summary.push('\n');
+        }
+        summary
+    }
+}
+
+fn product_without_variations(product: &Product) -> Product {
+    Product {
+        variations: vec![],
+        ..product.clone()
+    }
+}
+
+fn describe_product_changes(previous: &Product, current: &Product) -> Vec<String> {
+    let mut changes = vec![];
+    let mut compare = |field: &str, previous: String, current: String| {
+        if previous != current {
+            changes.push(format!("{} from '{}' to '{}'", field, previous, current));
+        }
+    };
+    compare("name", previous.name.clone(), current.name.clone());
+    compare(
+        "stock",
+        format!("{:?}", previous.stock),
+        format!("{:?}", current.stock),
+    );
+    compare(
+        "price",
+        previous.price.to_string(),
+        current.price.to_string(),
+    );
+    compare(
+        "cost price",
+        previous
+            .price_cost
+            .map(|p| p.to_string())
+            .unwrap_or_default(),
+        current
+            .price_cost
+            .map(|p| p.to_string())
+            .unwrap_or_default(),
+    );
+    compare(
+        "vendor",
+        previous.vendor_name.clone(),
+        current.vendor_name.clone(),
+    );
+    compare(
+        "category",
+        previous.categories.join(PATH_SEPARATOR),
+        current.categories.join(PATH_SEPARATOR),
+    );
+    compare("slug", previous.slug.clone(), current.slug.clone());
+    compare(
+        "availability",
+        format!("{:?}", previous.availability),
+        format!("{:?}", current.availability),
+    );
+    if previous.description != current.description {
+        changes.push("description".to_owned());
+    }
+    if previous.pictures != current.pictures {
+        changes.push("pictures".to_owned());
+    }
+    if changes.is_empty()
+        && product_without_variations(previous) != product_without_variations(current)
+    {
+        changes.push("other fields".to_owned());
+    }
+    changes
+}
+
+fn describe_variation_changes(previous: &Variation, current: &Variation) -> Vec<String> {
+    let mut changes = vec![];
+    if previous.price != current.price {
+        changes.push(format!(
+            "price from '{}' to '{}'",
+            previous.price, current.price
+        ));
+    }
+    if previous.picture != current.picture {
+        changes.push("picture".to_owned());
+    }
+    if changes.is_empty() {
+        changes.push("other fields".to_owned());
+    }
+    changes
+}
+
+fn variation_key(variation: &Variation) -> (String, Option<String>, Option<String>) {
+    (
+        variation.name1.clone(),
+        variation.name2.clone(),
+        variation.name3.clone(),
+    )
+}
+
+fn variation_name(variation: &Variation) -> String {
+    [
+        Some(&variation.name1),
+        variation.name2.as_ref(),
+        variation.name3.as_ref(),
+    ]
+    .iter()
+    .flatten()
+    .map(|name| name.as_str())
+    .collect::<Vec<_>>()
+    .join(" / ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::Money;
+    use pretty_assertions::assert_eq;
+
+    fn variation(name: &str, price: i64) -> Variation {
+        Variation {
+            type1: "Tamanho".to_owned(),
+            name1: name.to_owned(),
+            price: Money::from_cents(price),
+            ..Default::default()
+        }
+    }
+
+    fn product(id: &str, price: i64, variations: Vec<Variation>) -> Product {
+        Product::test(id)
+            .with_price(price)
+            .with_variations(variations)
+    }
+
+    fn ids(products: &[Product]) -> Vec<(&str, usize)> {
+        products
+            .iter()
+            .map(|p| (p.id.as_str(), p.variations.len()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_new_changed_and_removed_products_and_variations() {
+        let previous = vec![
+            product("1", 1000, vec![variation("P", 1000), variation("M", 1000)]),
+            product("2", 1000, vec![]),
+            product("3", 1000, vec![]),
+        ];
+        let mut current = vec![
+            product("1", 1000, vec![variation("P", 1200), variation("G", 1000)]),
+            product("3", 1500, vec![]),
+            product("4", 1000, vec![variation("U", 1000)]),
+        ];
+        current[1].slug = "product-3".to_owned();
+        let changes = diff(previous, &current);
+        assert_eq!(vec![("4", 1)], ids(&changes.created));
+        assert_eq!(vec![("3", 0)], ids(&changes.updated));
+        assert_eq!(vec![("2", 0)], ids(&changes.deleted));
+        assert_eq!(vec![("1", 1)], ids(&changes.variations_created));
+        assert_eq!(vec![("1", 1)], ids(&changes.variations_updated));
+        assert_eq!(vec![("1", 1)], ids(&changes.variations_deleted));
+        assert_eq!(
+            "Products: 1 new, 1 changed, 1 removed.\n\
+             Variations: 2 new, 1 changed, 1 removed.\n\
+             Removed product 2 'Product 2'.\n\
+             Changed variation 'P' of product 1: price from '10.00' to '12.00'.\n\
+             New variation 'G' of product 1.\n\
+             Removed variation 'M' of product 1.\n\
+             Changed product 3 'Product 3': price from '10.00' to '15.00', slug from '' to 'product-3'.\n\
+             New product 4 'Product 4'.\n",
+            changes.summary()
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
+};
+
+pub enum Page {
+    Found(String),
+    Slow(String, Duration),
+    NotFound,
+}
+
+impl Page {
+    // A page recorded from a store, at tests/fixtures/pages.
+    pub fn recorded(name: &str) -> Page {
+        Page::Found(read_recorded(name))
+    }
+
+    pub fn recorded_slow(name: &str, delay: Duration) -> Page {
+        Page::Slow(read_recorded(name), delay)
+    }
+}
+
+fn read_recorded(name: &str) -> String {
+    let file = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("pages")
+        .join(name);
+    fs::read_to_string(&file).unwrap_or_else(|e| panic!("Could not read {:?}: {}", file, e))
+}
+
+// Serves the pages by path, like "/pd-1", from a local port, until the test
+// process ends. Paths without a page get a 404.
+pub fn start_store(pages: Vec<(&str, Page)>) -> String {
+    let pages = pages
+        .into_iter()
+        .map(|(path, page)| (path.to_owned(), page))
+        .collect::<HashMap<_, _>>();
+    let pages: &'static HashMap<String, Page> = Box::leak(Box::new(pages));
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Could not start store.");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || respond(stream, pages));
+        }
+    });
+    url
+}
+
+fn respond(mut stream: TcpStream, pages: &HashMap<String, Page>) {
+    let mut request = vec![];
+    let mut buffer = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let (status, body) = match pages.get(path) {
+        Some(Page::Found(body)) => ("200 OK", body.as_str()),
+        Some(Page::Slow(body, delay)) => {
+            thread::sleep(*delay);
+            ("200 OK", body.as_str())
+        }
+        Some(Page::NotFound) | None => ("404 Not Found", "<html><body>Not found</body></html>"),
+    };
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+}
+
+// A directory in the system temp dir, removed with everything in it when the
+// test ends.
+pub struct TempDir(PathBuf);
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub fn temp_dir() -> TempDir {
+    let dir = std::env::temp_dir().join(format!(
+        "iluria-export-test-{}",
+        rand::thread_rng().gen::<u64>()
+    ));
+    fs::create_dir_all(&dir).expect("Could not create temp dir.");
+    TempDir(dir)
+}
+
+// Writes the Iluria stock report the way Iluria does, in Windows-1252.
+pub fn write_report(dir: &Path, rows: &[&str]) -> PathBuf {
+    write_report_with_header(
+        dir,
+        "Produto;Nome;Estoque;Preço;Preço de custo;Nome do fornecedor",
+        rows,
+    )
+}
+
+pub fn write_report_with_header(dir: &Path, header: &str, rows: &[&str]) -> PathBuf {
+    let mut text = format!("{}\n", header);
+    for row in rows {
+        text.push_str(row);
+        text.push('\n');
+    }
+    let file = dir.join("relatorio.csv");
+    fs::write(&file, encoding_rs::WINDOWS_1252.encode(&text).0).expect("Could not write report.");
+    file
+}
+
+// A CSV file written by the export, whose columns are found by their header,
+// so adding a column doesn't move the ones the tests check.
+pub struct Output {
+    headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Output {
+    fn position(&self, header: &str) -> usize {
+        self.headers
+            .iter()
+            .position(|h| h == header)
+            .unwrap_or_else(|| panic!("No column {} in {:?}", header, self.headers))
+    }
+
+    pub fn column(&self, header: &str) -> Vec<&str> {
+        let position = self.position(header);
+        self.rows.iter().map(|row| row[position].as_str()).collect()
+    }
+
+    pub fn values(&self, row: usize, headers: &[&str]) -> Vec<&str> {
+        headers
+            .iter()
+            .map(|header| self.rows[row][self.position(header)].as_str())
+            .collect()
+    }
+
+    pub fn value(&self, row: usize, header: &str) -> &str {
+        &self.rows[row][self.position(header)]
+    }
+}
+
+pub fn read_output(file: &Path) -> Output {
+    let bytes = fs::read(file).unwrap_or_else(|e| panic!("Could not read {:?}: {}", file, e));
+    let text = encoding_rs::WINDOWS_1252.decode(&bytes).0.into_owned();
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    Output {
+        headers: reader
+            .headers()
+            .unwrap()
+            .iter()
+            .map(|h| h.to_owned())
+            .collect(),
+        rows: reader
+            .records()
+            .map(|record| record.unwrap().iter().map(|v| v.to_owned()).collect())
+            .collect(),
+    }
+}
+
+pub fn run_export(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_iluria-export"))
+        .args(args)
+        .env("NO_PROXY", "127.0.0.1")
+        .output()
+        .expect("Could not run iluria-export.")
+}
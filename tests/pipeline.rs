@@ -0,0 +1,384 @@
+mod common;
+
+use common::{
+    read_output, run_export, start_store, temp_dir, write_report, write_report_with_header, Page,
+};
+use pretty_assertions::assert_eq;
+use std::time::Duration;
+
+#[test]
+fn exports_products_read_from_the_store() {
+    let url = start_store(vec![
+        ("/pd-1", Page::recorded("pd-1.html")),
+        ("/pd-2", Page::recorded("pd-2.html")),
+        ("/pd-3", Page::recorded("pd-3.html")),
+    ]);
+    let dir = temp_dir();
+    let report = write_report(
+        &dir,
+        &[
+            "1;Camiseta Básica;5;49,90;20,00;Malharia",
+            "1;Camiseta Básica;5;49,90;20,00;Malharia",
+            "2;Caneca;;25,00;;",
+            "3;Vela Aromática;2;1.250,00;;Velas & Cia",
+        ],
+    );
+    let shipping_defaults = dir.join("shipping.toml");
+    std::fs::write(
+        &shipping_defaults,
+        "[categories.casa]\nweight = 400\nheight = 10\nwidth = 10\nlength = 10\n",
+    )
+    .unwrap();
+    let output = run_export(&[
+        report.to_str().unwrap(),
+        &url,
+        "-o",
+        dir.to_str().unwrap(),
+        "--shipping-defaults",
+        shipping_defaults.to_str().unwrap(),
+        "--new-url",
+        "https://nova.com.br",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let products = read_output(&dir.join("products.csv"));
+    assert_eq!(vec!["1", "2", "3"], products.column("id"));
+    assert_eq!(
+        vec!["camiseta-basica", "caneca", "vela-aromatica"],
+        products.column("slug")
+    );
+    assert_eq!(vec!["59.90", "25.00", "1250.00"], products.column("price"));
+    let promotion = [
+        "promotional_price",
+        "promotion_start",
+        "promotion_end",
+        "installments",
+        "installment_value",
+        "interest_free",
+    ];
+    assert_eq!(
+        vec!["49.90", "01/12/2021", "31/12/2021", "3", "16.63", "Sim"],
+        products.values(0, &promotion)
+    );
+    assert_eq!(vec!["", "", "", "", "", ""], products.values(1, &promotion));
+    assert_eq!(
+        vec![
+            "<p>Camiseta de algodão.</p>",
+            "<p>Caneca de porcelana, 300 ml.</p>",
+            "<p>Vela de cera de soja.</p>"
+        ],
+        products.column("description")
+    );
+    assert_eq!(vec!["Roupas", "Casa", "Casa"], products.column("category"));
+    assert_eq!(vec!["Camisetas", "", ""], products.column("subcategory"));
+    let shipping = ["weight", "height", "width", "length"];
+    assert_eq!(vec!["200", "3", "25", "30"], products.values(0, &shipping));
+    assert_eq!(vec!["400", "10", "10", "10"], products.values(1, &shipping));
+    assert_eq!(
+        vec![
+            "Camiseta Básica - Loja Teste".to_owned(),
+            "Camiseta básica de algodão, várias cores.".to_owned(),
+            "camiseta, algodão, básica".to_owned(),
+            "https://nova.com.br/camiseta-basica".to_owned(),
+            format!("{}/pd-1", url)
+        ],
+        products.values(
+            0,
+            &[
+                "seo_title",
+                "seo_description",
+                "seo_keywords",
+                "canonical_url",
+                "old_url"
+            ]
+        )
+    );
+    assert_eq!(
+        vec!["CAM-001", "7891000315507", "Malharia"],
+        products.values(0, &["sku", "gtin", "brand"])
+    );
+    assert_eq!(
+        vec![
+            "http://img.iluria.com/1/camiseta-1.jpg",
+            "",
+            "https://img.iluria.com/3/vela.jpg"
+        ],
+        products.column("picture1")
+    );
+    assert_eq!(
+        "http://img.iluria.com/1/camiseta-2.jpg",
+        products.value(0, "picture2")
+    );
+
+    let variations = read_output(&dir.join("variations.csv"));
+    assert_eq!(
+        vec![
+            vec![
+                "1-branca-p",
+                "1",
+                "Tamanho",
+                "P",
+                "Cor",
+                "Branca",
+                "",
+                "",
+                "59.90",
+                "49.90",
+                "CAM-001-P",
+                "7891000100103",
+                "http://img.iluria.com/1/camiseta-p.jpg"
+            ],
+            vec![
+                "1-branca-m",
+                "1",
+                "Tamanho",
+                "M",
+                "Cor",
+                "Branca",
+                "",
+                "",
+                "54.90",
+                "",
+                "",
+                "",
+                ""
+            ],
+        ],
+        variations.rows
+    );
+
+    let categories = read_output(&dir.join("categories.csv"));
+    assert_eq!(
+        vec!["Roupas", "Camisetas", "Casa"],
+        categories.column("name")
+    );
+    let lint = read_output(&dir.join("lint.csv"));
+    assert_eq!(
+        vec![("2", "no-pictures")],
+        lint.column("product_id")
+            .into_iter()
+            .zip(lint.column("rule"))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn exports_products_not_found_or_unavailable_as_inactive() {
+    let url = start_store(vec![
+        ("/pd-1", Page::recorded("pd-1.html")),
+        ("/pd-2", Page::NotFound),
+        ("/pd-3", Page::recorded("pd-3.html")),
+        ("/pd-4", Page::recorded("pd-4.html")),
+    ]);
+    let dir = temp_dir();
+    let report = write_report(
+        &dir,
+        &[
+            "1;Camiseta Básica;5;49,90;;",
+            "2;Caneca;;25,00;;",
+            "3;Vela Aromática;0;1.250,00;;",
+            "4;Almofada;3;80,00;;",
+        ],
+    );
+    let output = run_export(&[report.to_str().unwrap(), &url, "-o", dir.to_str().unwrap()]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let products = read_output(&dir.join("products.csv"));
+    assert_eq!(vec!["1", "2", "3", "4"], products.column("id"));
+    assert_eq!(vec!["Sim", "Não", "Não", "Não"], products.column("active"));
+    assert_eq!(
+        vec!["Caneca", "25.00", ""],
+        products.values(1, &["name", "price", "description"])
+    );
+    assert_eq!(
+        "<p>Almofada de linho.</p>",
+        products.value(3, "description")
+    );
+    let run_report = std::fs::read_to_string(dir.join("report.md")).unwrap();
+    assert!(run_report.contains("| Inactive products | 3 |"));
+    assert!(run_report.contains(&format!("| 2 | [Caneca]({}/pd-2) | Page not found |", url)));
+    assert!(run_report.contains(&format!(
+        "| 3 | [Vela Aromática]({}/pd-3) | Out of stock |",
+        url
+    )));
+    assert!(run_report.contains(&format!("| 4 | [Almofada]({}/pd-4) | Unavailable |", url)));
+}
+
+#[test]
+fn stops_when_a_page_takes_longer_than_the_timeout() {
+    let url = start_store(vec![(
+        "/pd-1",
+        Page::recorded_slow("pd-1.html", Duration::from_millis(2500)),
+    )]);
+    let dir = temp_dir();
+    let report = write_report(&dir, &["1;Camiseta Básica;5;49,90;;"]);
+    let output = run_export(&[
+        report.to_str().unwrap(),
+        &url,
+        "-o",
+        dir.to_str().unwrap(),
+        "--timeout",
+        "1",
+    ]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(&format!(
+            "The page of product 1 at {}/pd-1 took more than 1 seconds",
+            url
+        )),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!dir.join("products.csv").exists());
+}
+
+#[test]
+fn downloads_the_images_of_descriptions() {
+    let url = start_store(vec![
+        ("/pd-5", Page::recorded("pd-5.html")),
+        (
+            "/imagens/Tabela%20de%20Medidas.PNG",
+            Page::Found("imagem".to_owned()),
+        ),
+    ]);
+    let dir = temp_dir();
+    let pictures = dir.join("fotos");
+    std::fs::create_dir(&pictures).unwrap();
+    let report = write_report(&dir, &["5;Toalha de Mesa;1;90,00;;"]);
+    let output = run_export(&[
+        report.to_str().unwrap(),
+        &url,
+        "-o",
+        dir.to_str().unwrap(),
+        "--pictures-dir",
+        pictures.to_str().unwrap(),
+        "--pictures-url",
+        "https://cdn.nova.com.br/fotos/",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let files = std::fs::read_dir(&pictures)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(1, files.len(), "{:?}", files);
+    assert!(files[0].starts_with("tabela-de-medidas-"));
+    assert_eq!(
+        "imagem",
+        std::fs::read_to_string(pictures.join(&files[0])).unwrap()
+    );
+    let products = read_output(&dir.join("products.csv"));
+    assert_eq!(
+        format!(
+            "<p>Toalha de linho.</p>\
+             <img src=\"https://cdn.nova.com.br/fotos/{}\">\
+             <img src=\"{}/imagens/quebrada.png\">",
+            files[0], url
+        ),
+        products.value(0, "description")
+    );
+}
+
+#[test]
+fn exports_products_read_from_a_nuvemshop_store() {
+    let url = start_store(vec![(
+        "/produtos/camiseta-basica/",
+        Page::recorded("nuvemshop-camiseta-basica.html"),
+    )]);
+    let dir = temp_dir();
+    let report = write_report_with_header(
+        &dir,
+        "Produto;Nome;Estoque;Preço;Preço de custo;Nome do fornecedor;Handle",
+        &["10;Camiseta Básica;3;49,90;;;camiseta-basica"],
+    );
+    let output = run_export(&[
+        report.to_str().unwrap(),
+        &url,
+        "-o",
+        dir.to_str().unwrap(),
+        "--source",
+        "nuvemshop",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let products = read_output(&dir.join("products.csv"));
+    assert_eq!(vec!["10"], products.column("id"));
+    assert_eq!(
+        vec![
+            "Sim",
+            "59.90",
+            "49.90",
+            "<p>Camiseta de algodão.</p>",
+            "Roupas",
+            "Camisetas"
+        ],
+        products.values(
+            0,
+            &[
+                "active",
+                "price",
+                "promotional_price",
+                "description",
+                "category",
+                "subcategory"
+            ]
+        )
+    );
+    assert_eq!(
+        format!("{}/produtos/camiseta-basica/", url),
+        products.value(0, "old_url")
+    );
+    assert_eq!(
+        vec!["NUV-010", "Malharia"],
+        products.values(0, &["sku", "brand"])
+    );
+    assert_eq!(
+        vec![
+            "https://d2r9epyceweg5n.cloudfront.net/stores/001/camiseta-1.jpg",
+            "https://d2r9epyceweg5n.cloudfront.net/stores/001/camiseta-2.jpg"
+        ],
+        products.values(0, &["picture1", "picture2"])
+    );
+
+    let variations = read_output(&dir.join("variations.csv"));
+    assert_eq!(vec!["10-branca-p", "10-branca-m"], variations.column("id"));
+    assert_eq!(
+        vec!["59.90", "49.90", "NUV-010-P", "7891000100103"],
+        variations.values(0, &["price", "promotional_price", "sku", "gtin"])
+    );
+    assert_eq!(
+        vec!["54.90", "", "", ""],
+        variations.values(1, &["price", "promotional_price", "sku", "gtin"])
+    );
+}
+
+#[test]
+fn refuses_nuvemshop_products_without_a_handle() {
+    let dir = temp_dir();
+    let report = write_report(&dir, &["Camiseta 10;Camiseta Básica;3;49,90;;"]);
+    let output = run_export(&[
+        report.to_str().unwrap(),
+        "http://127.0.0.1:9",
+        "-o",
+        dir.to_str().unwrap(),
+        "--source",
+        "nuvemshop",
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Product Camiseta 10 has no Nuvemshop handle, 'Camiseta 10' is not one."));
+}